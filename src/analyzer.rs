@@ -0,0 +1,465 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use super::language::Language;
+use super::lexer::Lexer;
+use super::model::Field;
+use super::snowball::SnowballEnv;
+
+/// The stop words of Lucene's `EnglishAnalyzer`.
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tokenizer {
+    /// The words on the Unicode word boundaries.
+    Words,
+    /// The words plus the punctuation and the symbols.
+    WordsAndSymbols,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenFilter {
+    /// NFKC normalization and case folding.
+    Lowercase,
+    /// Strips the diacritics and spells out the special Latin letters, so `Æsir` becomes `AEsir`.
+    AsciiFolding,
+    /// Snowball stemmer of the language. The documents of the other languages get the stemmers
+    /// of their own (see `Stemming`).
+    Stem(Language),
+    /// Drops the terms of the set.
+    StopWords(Arc<BTreeSet<String>>),
+    /// Adds the synonyms of the terms at the same position.
+    Synonyms(Arc<BTreeMap<String, Vec<String>>>),
+    /// Adds the char n-grams of the terms at the same position, so the parts of the words match.
+    NGrams { min: usize, max: usize },
+}
+
+/// The languages the `Stem` filters stem the words in.
+#[derive(Clone, Copy, Debug)]
+pub enum Stemming<'a> {
+    /// The language of the filter.
+    Default,
+    /// The language of the document.
+    Only(Language),
+    /// The language of the filter plus the ones of the documents in the index. The queries may be
+    /// looking for any of the documents.
+    Also(&'a [Language]),
+}
+
+impl Stemming<'_> {
+    /// The detected language of a document, if any, overrides the one of the filter.
+    pub fn of_doc(language: Option<Language>) -> Stemming<'static> {
+        language.map(Stemming::Only).unwrap_or(Stemming::Default)
+    }
+}
+
+impl TokenFilter {
+    /// Takes the terms of a single position. An empty result drops the token but not its
+    /// position, so the phrases don't match across the gap.
+    fn apply(&self, terms: Vec<String>, stemming: Stemming) -> Vec<String> {
+        let mut result = Vec::with_capacity(terms.len());
+        let mut push = |term: String| {
+            if !result.contains(&term) {
+                result.push(term);
+            }
+        };
+        match self {
+            TokenFilter::Lowercase => terms.into_iter().map(|term| fold_case(&term.nfkc().collect::<String>())).for_each(push),
+            TokenFilter::AsciiFolding => terms.into_iter().map(|term| fold_ascii(&term)).for_each(push),
+            TokenFilter::Stem(language) => {
+                for term in terms {
+                    match stemming {
+                        Stemming::Default => push(stem(&term, *language)),
+                        Stemming::Only(language) => push(stem(&term, language)),
+                        Stemming::Also(languages) => {
+                            push(stem(&term, *language));
+                            languages.iter().for_each(|language| push(stem(&term, *language)));
+                        }
+                    }
+                }
+            }
+            TokenFilter::StopWords(stop_words) => terms.into_iter().filter(|term| !stop_words.contains(term)).for_each(push),
+            TokenFilter::Synonyms(synonyms) => {
+                for term in terms {
+                    let alternatives = synonyms.get(&term).cloned().unwrap_or_default();
+                    push(term);
+                    alternatives.into_iter().for_each(&mut push);
+                }
+            }
+            TokenFilter::NGrams { min, max } => {
+                for term in terms {
+                    let chars = term.chars().collect::<Vec<_>>();
+                    push(term);
+                    // The whole term is already there
+                    for n in *min..=(*max).min(chars.len().saturating_sub(1)) {
+                        chars.windows(n).map(|gram| gram.iter().collect()).for_each(&mut push);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Display for TokenFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenFilter::Lowercase => write!(f, "lowercase"),
+            TokenFilter::AsciiFolding => write!(f, "ascii-folding"),
+            TokenFilter::Stem(language) => write!(f, "stem:{name}", name = language.name()),
+            TokenFilter::StopWords(stop_words) => write!(f, "stop-words:<{count} words>", count = stop_words.len()),
+            TokenFilter::Synonyms(synonyms) => write!(f, "synonyms:<{count} words>", count = synonyms.len()),
+            TokenFilter::NGrams { min, max } => write!(f, "ngrams:{min}-{max}"),
+        }
+    }
+}
+
+/// Turns the text into the terms. The tokenizer splits the text into tokens and the filters turn
+/// every token into the terms of its position one after another.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Analyzer {
+    pub tokenizer: Tokenizer,
+    pub filters: Vec<TokenFilter>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Analyzer {
+            tokenizer: Tokenizer::Words,
+            filters: vec![TokenFilter::Lowercase, TokenFilter::Stem(Language::English)],
+        }
+    }
+}
+
+/// The analyzers of the fields indexed by `Field::index`.
+pub type FieldAnalyzers = [Analyzer; Field::ALL.len()];
+
+/// The terms of a single position in the text. The first one comes from the token itself, the
+/// rest are its alternatives added by the filters (synonyms, n-grams). Empty if the filters dropped
+/// the token (stop words).
+pub type Terms = Vec<String>;
+
+impl Analyzer {
+    /// Returns the terms of every position along with the range of bytes they were produced from.
+    /// Every token takes a position, even if the filters drop all of its terms.
+    pub fn analyze<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (Terms, Range<usize>)> + 'a {
+        self.analyze_with(text, Stemming::Default)
+    }
+
+    /// The text is borrowed, not copied, for as long as the terms are being produced.
+    pub fn analyze_with<'a>(&'a self, text: &'a str, stemming: Stemming<'a>) -> impl Iterator<Item = (Terms, Range<usize>)> + 'a {
+        Lexer::new(text, self.tokenizer == Tokenizer::WordsAndSymbols).map(move |(token, span)| {
+            // The token as it was before the first stemmer, the stems depend on the language
+            let mut unstemmed = None;
+            let terms = self.filters.iter().fold(vec![token.to_string()], |terms, filter| match (filter, &unstemmed) {
+                (TokenFilter::Stem(_), None) => {
+                    unstemmed = terms.first().cloned();
+                    filter.apply(terms, stemming)
+                }
+                (TokenFilter::StopWords(stop_words), Some(token)) if stop_words.contains(token) => vec![],
+                (TokenFilter::StopWords(_), Some(_)) => terms,
+                _ => filter.apply(terms, stemming),
+            });
+            (terms, span)
+        })
+    }
+
+    /// Parses the analyzer from the comma separated list of the tokenizer and the filters, e.g.
+    /// `words,lowercase,stop-words:english,stem`. See `usage()` for all of them.
+    pub fn parse(spec: &str) -> Result<Analyzer, ()> {
+        let mut parts = spec.split(',').map(str::trim);
+        let tokenizer = match parts.next() {
+            Some("words") => Tokenizer::Words,
+            Some("words-and-symbols") => Tokenizer::WordsAndSymbols,
+            name => {
+                eprintln!("ERROR: unknown tokenizer {name}. Available tokenizers: words, words-and-symbols",
+                          name = name.unwrap_or_default());
+                return Err(());
+            }
+        };
+        let mut analyzer = Analyzer { tokenizer, filters: vec![] };
+        for part in parts {
+            let (name, arg) = part.split_once(':').unwrap_or((part, ""));
+            let filter = match name {
+                "lowercase" => TokenFilter::Lowercase,
+                "ascii-folding" => TokenFilter::AsciiFolding,
+                "stem" if arg.is_empty() => TokenFilter::Stem(Language::English),
+                "stem" => {
+                    let language = Language::from_name(arg).ok_or_else(|| {
+                        eprintln!("ERROR: unknown stemmer language {arg}. Available languages: {names}", names = Language::names());
+                    })?;
+                    TokenFilter::Stem(language)
+                }
+                "stop-words" => {
+                    let words = match arg {
+                        "" | "english" => ENGLISH_STOP_WORDS.iter().map(|word| word.to_string()).collect(),
+                        file_path => read_word_list(file_path)?,
+                    };
+                    // The words go through the same filters as the tokens they are matched against. After a
+                    // stemmer, they are matched against the tokens before it, so they don't depend on the
+                    // language of the document
+                    let unstemmed = analyzer.before_stemming();
+                    TokenFilter::StopWords(Arc::new(words.iter().filter_map(|word| unstemmed.analyze_word(word)).collect()))
+                }
+                "synonyms" if arg.is_empty() => {
+                    eprintln!("ERROR: no word list is provided for synonyms. Expected synonyms:<file>");
+                    return Err(());
+                }
+                "synonyms" => {
+                    let mut synonyms = BTreeMap::<String, Vec<String>>::new();
+                    for line in read_word_list(arg)? {
+                        let group = line.split(',').filter_map(|word| analyzer.analyze_word(word)).collect::<Vec<_>>();
+                        for term in &group {
+                            let alternatives = synonyms.entry(term.clone()).or_default();
+                            alternatives.extend(group.iter().filter(|other| *other != term).cloned());
+                        }
+                    }
+                    TokenFilter::Synonyms(Arc::new(synonyms))
+                }
+                "ngrams" => {
+                    let range = arg.split_once('-').and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)));
+                    match range {
+                        Some((min, max)) if 0 < min && min <= max => TokenFilter::NGrams { min, max },
+                        _ => {
+                            eprintln!("ERROR: invalid n-gram sizes {arg}. Expected <min>-<max>, e.g. ngrams:2-3");
+                            return Err(());
+                        }
+                    }
+                }
+                _ => {
+                    eprintln!("ERROR: unknown token filter {name}. Available filters: lowercase, ascii-folding, stem, stop-words, synonyms, ngrams");
+                    return Err(());
+                }
+            };
+            analyzer.filters.push(filter);
+        }
+        Ok(analyzer)
+    }
+
+    /// Switches the stemmers of the analyzer to the language.
+    pub fn set_language(&mut self, language: Language) {
+        for filter in &mut self.filters {
+            if let TokenFilter::Stem(current) = filter {
+                *current = language;
+            }
+        }
+    }
+
+    /// The analyzer up to the first stemmer.
+    fn before_stemming(&self) -> Analyzer {
+        let filters = self.filters.iter().take_while(|filter| !matches!(filter, TokenFilter::Stem(_))).cloned().collect();
+        Analyzer { tokenizer: self.tokenizer, filters }
+    }
+
+    /// The first term of a single word.
+    fn analyze_word(&self, word: &str) -> Option<String> {
+        self.analyze(word).next().and_then(|(terms, _)| terms.into_iter().next())
+    }
+}
+
+impl fmt::Display for Analyzer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tokenizer {
+            Tokenizer::Words => write!(f, "words")?,
+            Tokenizer::WordsAndSymbols => write!(f, "words-and-symbols")?,
+        }
+        for filter in &self.filters {
+            write!(f, ",{filter}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A word (or a comma separated group of words) per line. The lines starting with `#` are comments.
+fn read_word_list(file_path: &str) -> Result<Vec<String>, ()> {
+    let content = fs::read_to_string(file_path).map_err(|err| {
+        eprintln!("ERROR: could not read word list {file_path}: {err}");
+    })?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Lowercasing plus the few full case foldings that it misses, so `STRASSE` and `straße` match.
+fn fold_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c {
+            'ß' => result.push_str("ss"),
+            'ς' => result.push('σ'),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn fold_ascii(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'æ' => result.push_str("ae"),
+            'Æ' => result.push_str("AE"),
+            'œ' => result.push_str("oe"),
+            'Œ' => result.push_str("OE"),
+            'ß' => result.push_str("ss"),
+            'þ' => result.push_str("th"),
+            'Þ' => result.push_str("TH"),
+            'ø' => result.push('o'),
+            'Ø' => result.push('O'),
+            'ł' => result.push('l'),
+            'Ł' => result.push('L'),
+            'đ' | 'ð' => result.push('d'),
+            'Đ' | 'Ð' => result.push('D'),
+            'ı' => result.push('i'),
+            _ => result.push(c),
+        }
+    }
+    // The scripts without the ASCII equivalents stay composed
+    result.nfc().collect()
+}
+
+fn stem(term: &str, language: Language) -> String {
+    if !term.starts_with(char::is_alphabetic) {
+        return term.to_string();
+    }
+    let mut env = SnowballEnv::create(term);
+    language.stemmer()(&mut env);
+    env.get_current().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::ScratchPath;
+
+    fn terms(analyzer: &Analyzer, text: &str) -> Vec<Terms> {
+        analyzer.analyze(text).map(|(terms, _)| terms).collect()
+    }
+
+    fn firsts(analyzer: &Analyzer, text: &str) -> Vec<String> {
+        terms(analyzer, text).into_iter().map(|terms| terms[0].clone()).collect()
+    }
+
+    #[test]
+    fn lowercase() {
+        let analyzer = Analyzer::parse("words,lowercase").unwrap();
+        // NFKC turns the compatibility characters into the ordinary ones
+        assert_eq!(firsts(&analyzer, "ＡＢＣ ﬁsh Ⅻ"), ["abc", "fish", "xii"]);
+        assert_eq!(firsts(&analyzer, "STRASSE Straße"), ["strasse", "strasse"]);
+        assert_eq!(firsts(&analyzer, "ΟΔΟΣ οδος"), ["οδοσ", "οδοσ"]);
+    }
+
+    #[test]
+    fn ascii_folding() {
+        let analyzer = Analyzer::parse("words,ascii-folding,lowercase").unwrap();
+        assert_eq!(firsts(&analyzer, "Æsir crème brûlée Łódź Þór straße"), ["aesir", "creme", "brulee", "lodz", "thor", "strasse"]);
+        // No ASCII equivalents
+        assert_eq!(firsts(&analyzer, "東京 Ωmega"), ["東", "京", "ωmega"]);
+    }
+
+    #[test]
+    fn colon_splits_words() {
+        let analyzer = Analyzer::parse("words,lowercase").unwrap();
+        assert_eq!(firsts(&analyzer, "Foo:Bar"), ["foo", "bar"]);
+        assert_eq!(analyzer.analyze("foo:bar").map(|(_, span)| span).collect::<Vec<_>>(), [0..3, 4..7]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for spec in [
+            "", "letters", "lowercase", "words,uppercase", "words,stem:klingon", "words,synonyms", "words,synonyms:",
+            "words,stop-words:/nonexistent/seroost/stop-words.txt", "words,ngrams", "words,ngrams:3", "words,ngrams:0-2",
+            "words,ngrams:3-2", "words,ngrams:a-b", "words,ngrams:-2",
+        ] {
+            assert!(Analyzer::parse(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert_eq!(Analyzer::parse("words,lowercase,stem").unwrap(), Analyzer::default());
+        assert_eq!(Analyzer::parse(" words , lowercase ,stem:english").unwrap(), Analyzer::default());
+        assert_eq!(Analyzer::parse("words,stop-words").unwrap(), Analyzer::parse("words,stop-words:english").unwrap());
+        let analyzer = Analyzer::parse("words-and-symbols,ascii-folding,stem:german,ngrams:2-4").unwrap();
+        assert_eq!(analyzer.to_string(), "words-and-symbols,ascii-folding,stem:german,ngrams:2-4");
+        assert_eq!(Analyzer::parse(&analyzer.to_string()).unwrap(), analyzer);
+    }
+
+    #[test]
+    fn ngrams() {
+        let analyzer = Analyzer::parse("words,ngrams:2-3").unwrap();
+        assert_eq!(terms(&analyzer, "rust"), [vec!["rust", "ru", "us", "st", "rus", "ust"]]);
+        // The whole term is not repeated and the shorter terms get what they can
+        assert_eq!(terms(&analyzer, "abc ab a"), [vec!["abc", "ab", "bc"], vec!["ab"], vec!["a"]]);
+        let analyzer = Analyzer::parse("words,ngrams:1-1").unwrap();
+        assert_eq!(terms(&analyzer, "aba"), [vec!["aba", "a", "b"]]);
+    }
+
+    #[test]
+    fn synonyms() {
+        let list = ScratchPath::file("synonyms.txt", "# vehicles\ncar, Automobile\n\nquick,fast,speedy\n");
+        let analyzer = Analyzer::parse(&format!("words,lowercase,synonyms:{path}", path = list.0.display())).unwrap();
+        assert_eq!(terms(&analyzer, "Car automobile"), [vec!["car", "automobile"], vec!["automobile", "car"]]);
+        assert_eq!(terms(&analyzer, "fast bike"), [vec!["fast", "quick", "speedy"], vec!["bike"]]);
+    }
+
+    #[test]
+    fn filter_order() {
+        // The stop words go through the filters before them, not after them
+        let analyzer = Analyzer::parse("words,stop-words,lowercase").unwrap();
+        assert_eq!(terms(&analyzer, "The the cat"), [vec!["the"], vec![], vec!["cat"]]);
+        let analyzer = Analyzer::parse("words,lowercase,stop-words").unwrap();
+        assert_eq!(terms(&analyzer, "The the cat"), [vec![], vec![], vec!["cat"]]);
+        // The stop words after a stemmer are matched against the words before it
+        let list = ScratchPath::file("stop-words.txt", "Running\n");
+        let analyzer = Analyzer::parse(&format!("words,lowercase,stem,stop-words:{path}", path = list.0.display())).unwrap();
+        assert_eq!(terms(&analyzer, "runs running ran"), [vec!["run"], vec![], vec!["ran"]]);
+        // The n-grams of the stems
+        let analyzer = Analyzer::parse("words,lowercase,stem,ngrams:3-3").unwrap();
+        assert_eq!(terms(&analyzer, "Jumping"), [vec!["jump", "jum", "ump"]]);
+        let analyzer = Analyzer::parse("words,ngrams:3-3,lowercase").unwrap();
+        assert_eq!(terms(&analyzer, "JUMP"), [vec!["jump", "jum", "ump"]]);
+    }
+
+    #[test]
+    fn stop_words_of_other_languages() {
+        let list = ScratchPath::file("stop-words.txt", "und\ndie\n");
+        let analyzer = Analyzer::parse(&format!("words,lowercase,stem,stop-words:{path}", path = list.0.display())).unwrap();
+        let german = Stemming::Only(Language::German);
+        let terms = analyzer.analyze_with("Die Katzen und Hunde", german).map(|(terms, _)| terms).collect::<Vec<_>>();
+        assert_eq!(terms, [vec![], vec!["katz"], vec![], vec!["hund"]]);
+        let languages = [Language::German];
+        let terms = analyzer.analyze_with("die Katzen", Stemming::Also(&languages)).map(|(terms, _)| terms).collect::<Vec<_>>();
+        assert_eq!(terms, [vec![], vec!["katzen", "katz"]]);
+    }
+
+    #[test]
+    fn stems() {
+        let cases = [
+            (Language::English, ["running", "generously", "nationalization"], ["run", "generous", "nation"]),
+            (Language::German, ["häuser", "katzen", "aufeinanderfolgenden"], ["haus", "katz", "aufeinanderfolg"]),
+            (Language::French, ["chevaux", "continuellement", "majestueusement"], ["cheval", "continuel", "majestu"]),
+            (Language::Spanish, ["canciones", "corriendo", "niñas"], ["cancion", "corr", "niñ"]),
+            (Language::Russian, ["книгами", "столами", "красивая"], ["книг", "стол", "красив"]),
+            (Language::Portuguese, ["cavalos", "nacionalidade", "meninas"], ["caval", "nacional", "menin"]),
+        ];
+        for (language, words, stems) in cases {
+            assert_eq!(words.map(|word| stem(word, language)), stems, "{language:?}");
+        }
+        // Only the words
+        assert_eq!(stem("2024", Language::German), "2024");
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ignore::{Match, Walk, WalkBuilder};
+use ignore::gitignore::Gitignore;
+use ignore::overrides::{Override, OverrideBuilder};
+
+/// Project specific ignore file with the same syntax as `.gitignore`.
+const SEROOST_IGNORE: &str = ".seroostignore";
+/// The ignore files in the order of precedence.
+const IGNORE_FILES: [&str; 3] = [SEROOST_IGNORE, ".ignore", ".gitignore"];
+
+/// The parsed ignore files of the directories in the order of precedence.
+type IgnoreCache = HashMap<PathBuf, Vec<Gitignore>>;
+
+/// Decides which files of the folder get indexed. Skips the dot files, everything that is matched
+/// by the ignore files of the folder (including the nested ones) and the `--exclude` globs. If
+/// there are `--include` globs only the files that match them are indexed.
+#[derive(Clone)]
+pub struct FileFilter {
+    /// Canonical, so the paths of the documents are the same however the folder is spelled.
+    root: PathBuf,
+    overrides: Override,
+    /// Checking every document of a big folder would read the same ignore files over and over.
+    ignores: Arc<Mutex<IgnoreCache>>,
+}
+
+impl FileFilter {
+    pub fn new(root: &Path, includes: &[String], excludes: &[String]) -> Result<Self, ()> {
+        let metadata = fs::metadata(root).map_err(|err| {
+            eprintln!("ERROR: could not open directory {root} for indexing: {err}",
+                      root = root.display());
+        })?;
+        if !metadata.is_dir() {
+            eprintln!("ERROR: {root} is not a directory", root = root.display());
+            return Err(());
+        }
+        let root = fs::canonicalize(root).map_err(|err| {
+            eprintln!("ERROR: could not resolve directory {root}: {err}",
+                      root = root.display());
+        })?;
+
+        let mut builder = OverrideBuilder::new(&root);
+        let globs = includes.iter().cloned().chain(excludes.iter().map(|glob| format!("!{glob}")));
+        for glob in globs {
+            builder.add(&glob).map_err(|err| {
+                eprintln!("ERROR: invalid glob {glob}: {err}");
+            })?;
+        }
+        let overrides = builder.build().map_err(|err| {
+            eprintln!("ERROR: could not compile the globs: {err}");
+        })?;
+
+        Ok(Self { root, overrides, ignores: Default::default() })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Walks the files of `path` which is either the root or something inside of it. The ignore
+    /// files of the folders above `path` are taken into account.
+    pub fn walk(&self, path: &Path) -> Walk {
+        let path = path.to_path_buf();
+        WalkBuilder::new(&self.root)
+            .hidden(true)
+            // Only the ignore files inside of the folder matter, not the global ones
+            .parents(false)
+            .git_global(false)
+            .git_exclude(false)
+            .require_git(false)
+            .add_custom_ignore_filename(SEROOST_IGNORE)
+            .overrides(self.overrides.clone())
+            .filter_entry(move |entry| entry.path().starts_with(&path) || path.starts_with(entry.path()))
+            .build()
+    }
+
+    /// Same rules as `walk` but for a single path. The ignore files of a directory are read only
+    /// once (see `forget_ignore_file`).
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return true;
+        };
+        let mut ignores = self.ignores.lock().unwrap();
+        // Everything inside of an ignored folder is ignored regardless of its own rules, because
+        // the walk does not get there
+        let mut current = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            current.push(component);
+            let is_last = components.peek().is_none();
+            if self.is_ignored_itself(&mut ignores, &current, !is_last || is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Makes `is_ignored` read the ignore file again if `path` is one. Returns whether it is.
+    pub fn forget_ignore_file(&self, path: &Path) -> bool {
+        let is_ignore_file = path.file_name().map(|name| IGNORE_FILES.iter().any(|ignore_file| name == *ignore_file)).unwrap_or(false);
+        if let (true, Some(dir)) = (is_ignore_file, path.parent()) {
+            self.ignores.lock().unwrap().remove(dir);
+        }
+        is_ignore_file
+    }
+
+    /// The same order as the walk: the globs, the ignore files and only then the dot files.
+    fn is_ignored_itself(&self, ignores: &mut IgnoreCache, path: &Path, is_dir: bool) -> bool {
+        match self.overrides.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
+        }
+
+        // The deeper ignore files take precedence
+        for dir in path.ancestors().skip(1) {
+            if !ignores.contains_key(dir) {
+                ignores.insert(dir.to_path_buf(), read_ignore_files(dir));
+            }
+            for gitignore in &ignores[dir] {
+                match gitignore.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+            if dir == self.root {
+                break;
+            }
+        }
+        path.file_name().map(|name| name.to_string_lossy().starts_with('.')).unwrap_or(false)
+    }
+}
+
+fn read_ignore_files(dir: &Path) -> Vec<Gitignore> {
+    IGNORE_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|ignore_path| ignore_path.is_file())
+        .map(|ignore_path| Gitignore::new(ignore_path).0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use super::*;
+    use crate::scratch::ScratchPath;
+
+    fn all_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                all_files(&path, files);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    /// The files that `walk` and `is_ignored` let through, relative to the root.
+    fn indexed_files(filter: &FileFilter) -> (BTreeSet<String>, BTreeSet<String>) {
+        let relative = |path: &Path| path.strip_prefix(filter.root()).unwrap().to_string_lossy().into_owned();
+        let walked = filter.walk(filter.root())
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false))
+            .map(|entry| relative(entry.path()))
+            .collect();
+        let mut files = Vec::new();
+        all_files(filter.root(), &mut files);
+        let checked = files.iter().filter(|path| !filter.is_ignored(path, false)).map(|path| relative(path)).collect();
+        (walked, checked)
+    }
+
+    #[test]
+    fn walk_agrees_with_is_ignored() {
+        let dir = ScratchPath::dir("walk-agrees", &[
+            (".gitignore", "*.log\nbuild/\n"),
+            (".seroostignore", "!kept.log\n"),
+            ("a.txt", ""),
+            ("dropped.log", ""),
+            ("kept.log", ""),
+            ("notes.md", ""),
+            (".hidden.txt", ""),
+            ("sub/.gitignore", "!important.log\n*.md\n"),
+            ("sub/important.log", ""),
+            ("sub/other.log", ""),
+            ("sub/readme.md", ""),
+            ("sub/deep/c.txt", ""),
+            ("build/.gitignore", "!*\n"),
+            ("build/out.txt", ""),
+            ("gen/out.txt", ""),
+            ("gen/out.md", ""),
+        ]);
+        let expected = |files: &[&str]| files.iter().map(|file| file.to_string()).collect::<BTreeSet<_>>();
+
+        let filter = FileFilter::new(&dir.0, &[], &[]).unwrap();
+        let (walked, checked) = indexed_files(&filter);
+        assert_eq!(walked, checked);
+        assert_eq!(walked, expected(&["a.txt", "gen/out.md", "gen/out.txt", "kept.log", "notes.md", "sub/deep/c.txt", "sub/important.log"]));
+
+        let filter = FileFilter::new(&dir.0, &["*.txt".to_string(), "*.log".to_string()], &["gen/**".to_string()]).unwrap();
+        let (walked, checked) = indexed_files(&filter);
+        assert_eq!(walked, checked);
+        // The globs win over the ignore files and the dot files
+        assert_eq!(walked, expected(&[".hidden.txt", "a.txt", "dropped.log", "kept.log", "sub/deep/c.txt", "sub/important.log", "sub/other.log"]));
+    }
+
+    #[test]
+    fn changed_ignore_files() {
+        let dir = ScratchPath::dir("changed-ignore-files", &[("a.txt", ""), ("sub/b.txt", "")]);
+        let filter = FileFilter::new(&dir.0, &[], &[]).unwrap();
+        let b = filter.root().join("sub/b.txt");
+        assert!(!filter.is_ignored(&b, false));
+        let ignore_file = filter.root().join("sub/.ignore");
+        fs::write(&ignore_file, "b.txt\n").unwrap();
+        // Until the change is picked up
+        assert!(!filter.is_ignored(&b, false));
+        assert!(filter.forget_ignore_file(&ignore_file));
+        assert!(filter.is_ignored(&b, false));
+        assert!(!filter.forget_ignore_file(&b));
+        let (walked, checked) = indexed_files(&filter);
+        assert_eq!(walked, checked);
+    }
+
+    #[test]
+    fn root_is_canonical() {
+        let dir = ScratchPath::dir("root-is-canonical", &[("a.txt", ""), ("sub/b.txt", "")]);
+        let filter = FileFilter::new(&dir.0, &[], &[]).unwrap();
+        let respelled = FileFilter::new(&dir.0.join("sub").join(".."), &[], &[]).unwrap();
+        assert_eq!(filter.root(), respelled.root());
+        // The files walked with one spelling are not outside of the other one
+        let (walked, _) = indexed_files(&filter);
+        assert_eq!(walked.len(), 2);
+        for file in walked {
+            assert!(!respelled.is_ignored(&filter.root().join(&file), false), "{file}");
+        }
+    }
+}
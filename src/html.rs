@@ -0,0 +1,222 @@
+// A lenient HTML parser that extracts the text of the page. It does not build any tree and never
+// fails, so the broken markup of the real world pages just produces slightly worse text.
+
+use super::html_entities;
+use super::model::ParsedDoc;
+
+/// Elements with the content that is not text.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "noscript", "template"];
+/// Elements with the text that is the same on every page of a site.
+const BOILERPLATE_ELEMENTS: &[&str] = &["nav", "footer"];
+/// Elements that don't break the text into separate blocks.
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "cite", "code", "data", "dfn", "em", "font", "i", "kbd",
+    "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var",
+];
+
+struct Tag {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+}
+
+struct Parser<'a> {
+    source: &'a [char],
+    cursor: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn starts_with(&self, prefix: &str) -> bool {
+        let mut cursor = self.cursor;
+        for c in prefix.chars() {
+            match self.source.get(cursor) {
+                Some(x) if x.eq_ignore_ascii_case(&c) => cursor += 1,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Moves the cursor right after the first occurrence of `pattern` or to the end.
+    fn skip_past(&mut self, pattern: &str) {
+        while self.cursor < self.source.len() && !self.starts_with(pattern) {
+            self.cursor += 1;
+        }
+        self.cursor = (self.cursor + pattern.chars().count()).min(self.source.len());
+    }
+
+    /// Expects the cursor at `<` followed by a letter or `/`.
+    fn parse_tag(&mut self) -> Tag {
+        self.cursor += 1;
+        let closing = self.source.get(self.cursor) == Some(&'/');
+        if closing {
+            self.cursor += 1;
+        }
+        let mut name = String::new();
+        while let Some(c) = self.source.get(self.cursor).filter(|c| c.is_alphanumeric() || **c == '-') {
+            name.push(c.to_ascii_lowercase());
+            self.cursor += 1;
+        }
+        // Attributes
+        let mut quote = None;
+        let mut self_closing = false;
+        while let Some(&c) = self.source.get(self.cursor) {
+            self.cursor += 1;
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => break,
+                (None, '/') => self_closing = true,
+                (None, c) if !c.is_whitespace() => self_closing = false,
+                _ => {}
+            }
+        }
+        Tag { name, closing, self_closing }
+    }
+
+    /// Expects the cursor at `&`. Leaves the text as is if it's not a known entity.
+    fn parse_entity(&mut self) -> String {
+        let start = self.cursor;
+        let end = self.source[start..]
+            .iter()
+            .take(html_entities::MAX_NAME_LEN + 2)
+            .position(|c| *c == ';')
+            .map(|position| start + position);
+        if let Some(end) = end {
+            let entity = self.source[start + 1..end].iter().collect::<String>();
+            if let Some(decoded) = decode_entity(&entity) {
+                self.cursor = end + 1;
+                return decoded;
+            }
+        }
+        self.cursor += 1;
+        "&".to_string()
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<String> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code).map(String::from);
+    }
+    html_entities::decode(entity).map(str::to_string)
+}
+
+pub fn parse(source: &str) -> ParsedDoc {
+    let source = source.chars().collect::<Vec<_>>();
+    let mut parser = Parser { source: &source, cursor: 0 };
+    let mut doc = ParsedDoc::default();
+    let mut title = None;
+    let mut heading = None;
+    let mut boilerplate_depth = 0;
+
+    while let Some(&c) = parser.source.get(parser.cursor) {
+        if parser.starts_with("<!--") {
+            parser.skip_past("-->");
+            continue;
+        }
+        if parser.starts_with("<!") || parser.starts_with("<?") {
+            parser.skip_past(">");
+            continue;
+        }
+
+        let is_tag = c == '<' && parser.source.get(parser.cursor + 1).map(|c| c.is_alphabetic() || *c == '/').unwrap_or(false);
+        if is_tag {
+            let tag = parser.parse_tag();
+            let name = tag.name.as_str();
+            if !tag.closing && !tag.self_closing && RAW_TEXT_ELEMENTS.contains(&name) {
+                parser.skip_past(&format!("</{name}"));
+                parser.skip_past(">");
+                continue;
+            }
+            if BOILERPLATE_ELEMENTS.contains(&name) && !tag.self_closing {
+                if tag.closing {
+                    boilerplate_depth = usize::saturating_sub(boilerplate_depth, 1);
+                } else {
+                    boilerplate_depth += 1;
+                }
+                continue;
+            }
+            match (name, tag.closing) {
+                ("title", false) => title = Some(String::new()),
+                ("title", true) => {
+                    if let Some(title) = title.take() {
+                        if !doc.title.is_empty() {
+                            doc.title.push(' ');
+                        }
+                        doc.title.push_str(title.trim());
+                    }
+                }
+                ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => heading = Some(String::new()),
+                ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => doc.headings.extend(heading.take()),
+                _ => {}
+            }
+            if !INLINE_ELEMENTS.contains(&name) {
+                doc.body.push('\n');
+            }
+            continue;
+        }
+
+        let text = if c == '&' {
+            parser.parse_entity()
+        } else {
+            parser.cursor += 1;
+            c.to_string()
+        };
+        if let Some(title) = &mut title {
+            title.push_str(&text);
+        } else if boilerplate_depth == 0 {
+            doc.body.push_str(&text);
+            if let Some(heading) = &mut heading {
+                heading.push_str(&text);
+            }
+        }
+    }
+
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The body with the whitespace collapsed.
+    fn body(source: &str) -> String {
+        parse(source).body.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(body("<p>S&atilde;o Paulo &Agrave; la &oelig;uvre &iquest;qu&eacute;?</p>"), "São Paulo À la œuvre ¿qué?");
+        assert_eq!(body("&Ccedil;a &iexcl;s&iacute;! &ucirc; &otilde; &euro;&nbsp;5 &hellip;"), "Ça ¡sí! û õ € 5 …");
+        assert_eq!(body("&#233;t&#xE9; &#X263A; &CounterClockwiseContourIntegral;"), "été ☺ ∳");
+        // Several code points
+        assert_eq!(body("&NotEqualTilde;"), "\u{2242}\u{338}");
+        // Unknown, unterminated and broken entities stay as they are
+        assert_eq!(body("a &foo; b & c &amp d &#xZZ; &#1114112;"), "a &foo; b & c &amp d &#xZZ; &#1114112;");
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        let source = "<p>before</p><script>if (a < b) document.write(\"<p>script</p>\");</script>\
+                      <STYLE type=\"text/css\">p { color: red }</STYLE><noscript>noscript</noscript><p>after</p>";
+        assert_eq!(body(source), "before after");
+        assert_eq!(body("<p>text</p><script>never closed <p>more</p>"), "text");
+        assert_eq!(body("<p>a</p><nav><a href=\"/\">Home</a></nav><p>b</p><footer>Contact</footer>"), "a b");
+    }
+
+    #[test]
+    fn unclosed_tags() {
+        let doc = parse("<html><head><title>My &amp; Page</title><body><h1>Intro</h1><p>text <b>bold<p>next <a href='x>y'>link");
+        assert_eq!(doc.title, "My & Page");
+        assert_eq!(doc.headings, ["Intro"]);
+        assert_eq!(doc.body.split_whitespace().collect::<Vec<_>>(), ["Intro", "text", "bold", "next", "link"]);
+        assert_eq!(body("a < b <!-- comment never closed"), "a < b");
+        assert_eq!(body("<p>cut off <span"), "cut off");
+        assert_eq!(body("one<br/>two<img src=\"x.png\">three"), "one two three");
+    }
+}
@@ -1,20 +1,63 @@
 // TODO: live update results as you type
-async function search(prompt) {
+const PAGE_SIZE = 20;
+
+async function search(prompt, offset = 0) {
     const results = document.getElementById("results")
-    results.innerHTML = "";
+    if (offset == 0) {
+        results.innerHTML = "";
+    }
     const response = await fetch("/api/search", {
         method: 'POST',
-        headers: {'Content-Type': 'text/plain'},
-        body: prompt,
+        headers: {'Content-Type': 'application/json'},
+        body: JSON.stringify({query: prompt, offset, limit: PAGE_SIZE}),
     });
     const json = await response.json();
-    results.innerHTML = "";
-    for ([path, rank] of json) {
-        let item = document.createElement("span");
+    if (!response.ok) {
+        results.innerHTML = "";
+        results.appendChild(document.createTextNode(`Error: ${json.error.message}`));
+        return;
+    }
+    if (offset == 0) {
+        results.innerHTML = "";
+        let summary = document.createElement("p");
+        summary.appendChild(document.createTextNode(`${json.total} results in ${json.took_ms.toFixed(2)} ms`));
+        results.appendChild(summary);
+    } else {
+        results.removeChild(results.lastChild);
+    }
+    for ({path, snippets} of json.hits) {
+        let item = document.createElement("div");
         item.appendChild(document.createTextNode(path));
-        item.appendChild(document.createElement("br"));
+        for (snippet of snippets) {
+            item.appendChild(renderSnippet(snippet));
+        }
         results.appendChild(item);
     }
+    const nextOffset = json.offset + json.hits.length;
+    if (nextOffset < json.total) {
+        let more = document.createElement("button");
+        more.appendChild(document.createTextNode("More"));
+        more.addEventListener("click", () => {
+            currentSearch = currentSearch.then(() => search(prompt, nextOffset));
+        });
+        results.appendChild(more);
+    }
+}
+
+function renderSnippet({text, highlights}) {
+    // The highlights are counted in Unicode code points, not UTF-16 code units
+    const chars = Array.from(text);
+    let cursor = 0;
+    let snippet = document.createElement("p");
+    for ([start, end] of highlights) {
+        snippet.appendChild(document.createTextNode(chars.slice(cursor, start).join("")));
+        let mark = document.createElement("mark");
+        mark.appendChild(document.createTextNode(chars.slice(start, end).join("")));
+        snippet.appendChild(mark);
+        cursor = end;
+    }
+    snippet.appendChild(document.createTextNode(chars.slice(cursor).join("")));
+    return snippet;
 }
 
 let query = document.getElementById("query");
@@ -22,6 +65,6 @@ let currentSearch = Promise.resolve()
 
 query.addEventListener("keypress", (e) => {
     if (e.key == "Enter") {
-        currentSearch.then(() => search(query.value));
+        currentSearch = currentSearch.then(() => search(query.value));
     }
 })
@@ -0,0 +1,734 @@
+// Binary format of the index file. All the integers are little-endian.
+//
+// Header:
+//   magic            [u8; 8]   "SEROOST\0"
+//   version          u32
+//   reserved         u32
+//   docs_count       u64
+//   terms_count      u64
+//   meta             (offset u64, len u64)
+//   docs             (offset u64, len u64)
+//   terms            (offset u64, len u64)
+//   postings         (offset u64, len u64)
+//   texts            (offset u64, len u64)
+//   spans            (offset u64, len u64)
+//
+// Meta section:
+//   scorer           u8        0 - BM25, 1 - TF-IDF
+//   k1, b            f32, f32  only for BM25
+//   total_lengths    [u64; 4]  sums of the lengths of the body, title, headings and code of all the docs
+//   language_counts  [u64; 6]  the amounts of the docs of each language (see `Language::ALL`)
+//   analyzers_len    u64
+//   analyzers        JSON      the analyzers of the body, title, headings and code (see `Analyzer`)
+//
+// The file is designed to be mapped into memory and searched in place (see MappedIndex).
+//
+// Docs section: `docs_count` fixed size entries followed by the paths. The id of a document
+// is the index of its entry.
+//   path_offset      u64       relative to the beginning of the section
+//   path_len         u32
+//   body_len         u32       the lengths of the fields are counted in terms
+//   last_modified    u64 seconds + u32 nanoseconds since UNIX epoch
+//   language         u32       0 - not detected, otherwise 1 + index in `Language::ALL`
+//   text_offset      u64       relative to the beginning of the texts section
+//   text_len         u64
+//   title_len        u32
+//   headings_len     u32
+//   spans_offset     u64       relative to the beginning of the spans section
+//   spans_len        u64
+//   code_len         u32
+//
+// Terms section: `terms_count` fixed size entries sorted by the term followed by the terms.
+//   term_offset      u64       relative to the beginning of the section
+//   term_len         u32
+//   doc_freq         u32
+//   postings_offset  u64       relative to the beginning of the postings section
+//   postings_len     u64
+//
+// The terms of the fields other than the body are prefixed with `\0` and the index of the
+// field (see `Field::term_key`).
+//
+// Postings section: for each term `doc_freq` records of varints
+//   doc id delta, positions count, positions deltas...
+//
+// Texts section: the UTF-8 texts the documents were indexed from.
+//
+// Spans section: for each document the byte ranges of the tokens of the body in its text by
+// their positions, (start u32, end u32) each (see `Spans`). The snippets are made out of them.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use memmap2::Mmap;
+
+use super::model::*;
+use super::analyzer::FieldAnalyzers;
+use super::language::Language;
+
+pub const MAGIC: &[u8; 8] = b"SEROOST\0";
+pub const VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 128;
+pub const DOC_ENTRY_SIZE: usize = 76;
+pub const TERM_ENTRY_SIZE: usize = 32;
+
+const SCORER_BM25: u8 = 0;
+const SCORER_TFIDF: u8 = 1;
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub fn read_varint(bytes: &[u8], cursor: &mut usize) -> Option<u64> {
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*cursor)?;
+        *cursor += 1;
+        if shift >= 64 {
+            return None;
+        }
+        result |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(result);
+        }
+        shift += 7;
+    }
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+pub fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let bytes = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+pub fn read_f32(bytes: &[u8], offset: usize) -> Option<f32> {
+    read_u32(bytes, offset).map(f32::from_bits)
+}
+
+/// A range of the file referred by an (offset, len) pair.
+fn read_range(bytes: &[u8], offset: u64, len: u64) -> Option<Range<usize>> {
+    let start = usize::try_from(offset).ok()?;
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    if end > bytes.len() {
+        return None;
+    }
+    Some(start..end)
+}
+
+fn read_section(bytes: &[u8], offset: u64, len: u64) -> Option<&[u8]> {
+    read_range(bytes, offset, len).map(|range| &bytes[range])
+}
+
+pub fn parse_meta(meta: &[u8]) -> Option<(Scorer, FieldLengths, LanguageCounts, FieldAnalyzers)> {
+    let (scorer, offset) = match *meta.first()? {
+        SCORER_BM25 => {
+            let k1 = read_f32(meta, 1)?;
+            let b = read_f32(meta, 5)?;
+            (Scorer::Bm25 { k1, b }, 9)
+        }
+        SCORER_TFIDF => (Scorer::TfIdf, 1),
+        _ => return None,
+    };
+    let mut total_lengths = FieldLengths::default();
+    for (i, total) in total_lengths.iter_mut().enumerate() {
+        *total = read_u64(meta, offset + i*8)? as usize;
+    }
+    let offset = offset + total_lengths.len()*8;
+    let mut language_counts = LanguageCounts::default();
+    for (i, count) in language_counts.iter_mut().enumerate() {
+        *count = read_u64(meta, offset + i*8)? as usize;
+    }
+    let offset = offset + language_counts.len()*8;
+    let analyzers_len = read_u64(meta, offset)?;
+    // The filters are too varied for a binary layout, and the section is read only once
+    let analyzers = serde_json::from_slice(read_section(meta, offset as u64 + 8, analyzers_len)?).ok()?;
+    Some((scorer, total_lengths, language_counts, analyzers))
+}
+
+pub struct DocEntry<'a> {
+    pub path: &'a str,
+    pub lengths: FieldLengths,
+    pub language: Option<Language>,
+    pub last_modified: SystemTime,
+    pub text: &'a str,
+    pub spans: &'a [u8],
+}
+
+#[derive(Clone, Copy)]
+pub struct TermEntry<'a> {
+    pub term: &'a str,
+    pub doc_freq: usize,
+    pub postings: &'a [u8],
+}
+
+impl<'a> TermEntry<'a> {
+    pub fn postings(&self) -> PostingsIter<'a> {
+        PostingsIter { postings: self.postings, cursor: 0, left: self.doc_freq, id: None }
+    }
+}
+
+/// Iterates the postings of a single term decoding them as it goes. The positions are only
+/// skipped over until they are asked for (see `EncodedPositions::decode`). Stops at the first
+/// corrupted record.
+pub struct PostingsIter<'a> {
+    postings: &'a [u8],
+    cursor: usize,
+    left: usize,
+    id: Option<u64>,
+}
+
+impl<'a> PostingsIter<'a> {
+    fn read_record(&mut self) -> Option<(DocId, EncodedPositions<'a>)> {
+        let delta = read_varint(self.postings, &mut self.cursor)?;
+        let id = self.id.map_or(delta, |id| id.saturating_add(delta));
+        self.id = Some(id);
+        let count = read_varint(self.postings, &mut self.cursor)?;
+        let start = self.cursor;
+        // Every position takes at least one byte, so the corrupted counts run out of bytes quickly
+        for _ in 0..count {
+            read_varint(self.postings, &mut self.cursor)?;
+        }
+        let positions = EncodedPositions { bytes: &self.postings[start..self.cursor], count: count as usize };
+        Some((DocId::try_from(id).ok()?, positions))
+    }
+}
+
+impl<'a> Iterator for PostingsIter<'a> {
+    type Item = (DocId, EncodedPositions<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == 0 {
+            return None;
+        }
+        self.left -= 1;
+        let record = self.read_record();
+        if record.is_none() {
+            self.left = 0;
+        }
+        record
+    }
+}
+
+/// The positions of a term in a document as they are stored in the postings section.
+#[derive(Clone, Copy)]
+pub struct EncodedPositions<'a> {
+    bytes: &'a [u8],
+    count: usize,
+}
+
+impl EncodedPositions<'_> {
+    pub fn decode(&self) -> Vec<usize> {
+        let mut positions = Vec::with_capacity(self.count);
+        let mut cursor = 0;
+        let mut position = 0u64;
+        while let Some(delta) = read_varint(self.bytes, &mut cursor) {
+            position = position.saturating_add(delta);
+            positions.push(position as usize);
+        }
+        positions
+    }
+}
+
+/// The index file mapped into memory. Nothing is decoded upfront, the documents and the terms
+/// are looked up in the mapped tables on demand.
+pub struct MappedIndex {
+    mmap: Mmap,
+    pub docs_count: usize,
+    pub terms_count: usize,
+    pub scorer: Scorer,
+    pub total_lengths: FieldLengths,
+    pub language_counts: LanguageCounts,
+    pub analyzers: FieldAnalyzers,
+    docs: Range<usize>,
+    terms: Range<usize>,
+    postings: Range<usize>,
+    texts: Range<usize>,
+    spans: Range<usize>,
+}
+
+impl MappedIndex {
+    pub fn open(index_path: &Path) -> Result<Self, ()> {
+        let file = File::open(index_path).map_err(|err| {
+            eprintln!("ERROR: could not open index file {index_path}: {err}",
+                      index_path = index_path.display());
+        })?;
+        // SAFETY: the index file is only ever replaced by renaming a new file over it, never
+        // modified in place. The mapping keeps pointing to the old file in that case.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|err| {
+            eprintln!("ERROR: could not map index file {index_path} into memory: {err}",
+                      index_path = index_path.display());
+        })?;
+        Self::from_mmap(mmap).map_err(|err| {
+            eprintln!("ERROR: could not load index file {index_path}: {err}",
+                      index_path = index_path.display());
+        })
+    }
+
+    fn from_mmap(mmap: Mmap) -> Result<Self, String> {
+        let bytes = &mmap[..];
+        if bytes.len() < HEADER_SIZE || &bytes[0..8] != MAGIC {
+            return Err("not a Seroost index file".to_string());
+        }
+        let version = read_u32(bytes, 8).unwrap();
+        if version != VERSION {
+            return Err(format!("unsupported index format version {version}, expected {VERSION}"));
+        }
+        let section = |i: usize| {
+            let offset = read_u64(bytes, 32 + i*16).unwrap();
+            let len = read_u64(bytes, 32 + i*16 + 8).unwrap();
+            read_range(bytes, offset, len).ok_or_else(|| "section is out of bounds".to_string())
+        };
+        let meta = section(0)?;
+        let (scorer, total_lengths, language_counts, analyzers) = parse_meta(&bytes[meta]).ok_or_else(|| "meta section is corrupted".to_string())?;
+        let docs_count = read_u64(bytes, 16).unwrap() as usize;
+        let terms_count = read_u64(bytes, 24).unwrap() as usize;
+        let docs = section(1)?;
+        let terms = section(2)?;
+        let postings = section(3)?;
+        let texts = section(4)?;
+        let spans = section(5)?;
+        if docs.len() / DOC_ENTRY_SIZE < docs_count || docs_count > DocId::MAX as usize {
+            return Err("docs table is out of bounds".to_string());
+        }
+        if terms.len() / TERM_ENTRY_SIZE < terms_count {
+            return Err("terms table is out of bounds".to_string());
+        }
+        Ok(MappedIndex { mmap, docs_count, terms_count, scorer, total_lengths, language_counts, analyzers, docs, terms, postings, texts, spans })
+    }
+
+    pub fn doc(&self, id: DocId) -> Option<DocEntry<'_>> {
+        let id = id as usize;
+        if id >= self.docs_count {
+            return None;
+        }
+        let docs = &self.mmap[self.docs.clone()];
+        let texts = &self.mmap[self.texts.clone()];
+        let spans = &self.mmap[self.spans.clone()];
+        let entry = id*DOC_ENTRY_SIZE;
+        let path = read_section(docs, read_u64(docs, entry)?, read_u32(docs, entry + 8)? as u64)?;
+        let secs = read_u64(docs, entry + 16)?;
+        let nanos = read_u32(docs, entry + 24)?;
+        let mut lengths = FieldLengths::default();
+        lengths[Field::Body.index()] = read_u32(docs, entry + 12)? as usize;
+        lengths[Field::Title.index()] = read_u32(docs, entry + 48)? as usize;
+        lengths[Field::Headings.index()] = read_u32(docs, entry + 52)? as usize;
+        lengths[Field::Code.index()] = read_u32(docs, entry + 72)? as usize;
+        let language = match read_u32(docs, entry + 28)? {
+            0 => None,
+            n => Some(*Language::ALL.get(n as usize - 1)?),
+        };
+        Some(DocEntry {
+            path: std::str::from_utf8(path).ok()?,
+            lengths,
+            language,
+            last_modified: UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?,
+            text: std::str::from_utf8(read_section(texts, read_u64(docs, entry + 32)?, read_u64(docs, entry + 40)?)?).ok()?,
+            spans: read_section(spans, read_u64(docs, entry + 56)?, read_u64(docs, entry + 64)?)?,
+        })
+    }
+
+    pub fn term(&self, index: usize) -> Option<TermEntry<'_>> {
+        if index >= self.terms_count {
+            return None;
+        }
+        let terms = &self.mmap[self.terms.clone()];
+        let postings = &self.mmap[self.postings.clone()];
+        let entry = index*TERM_ENTRY_SIZE;
+        let term = read_section(terms, read_u64(terms, entry)?, read_u32(terms, entry + 8)? as u64)?;
+        Some(TermEntry {
+            term: std::str::from_utf8(term).ok()?,
+            doc_freq: read_u32(terms, entry + 12)? as usize,
+            postings: read_section(postings, read_u64(terms, entry + 16)?, read_u64(terms, entry + 24)?)?,
+        })
+    }
+
+    /// Binary searches the term in the sorted terms table.
+    pub fn find_term(&self, term: &str) -> Option<TermEntry<'_>> {
+        self.term(self.term_index(term)?).filter(|entry| entry.term == term)
+    }
+
+    /// The terms of the sorted terms table starting from the first one that is not less than `start`.
+    pub fn terms_from(&self, start: &str) -> impl Iterator<Item = &str> {
+        let index = self.term_index(start).unwrap_or(self.terms_count);
+        (index..self.terms_count).map_while(|index| self.term(index)).map(|entry| entry.term)
+    }
+
+    /// The index of the first term that is not less than `term`.
+    pub fn term_index(&self, term: &str) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.terms_count);
+        while lo < hi {
+            let mid = lo + (hi - lo)/2;
+            match self.term(mid)?.term.cmp(term) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater | Ordering::Equal => hi = mid,
+            }
+        }
+        Some(lo)
+    }
+}
+
+pub fn load_model(index_path: &Path) -> Result<Model, ()> {
+    MappedIndex::open(index_path).map(Model::from_index)
+}
+
+/// Keeps track of the offset in the output so the sections know where they start.
+struct Output<W: Write> {
+    inner: W,
+    offset: u64,
+}
+
+impl<W: Write> Output<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+fn encode_model(model: &Model, file: File) -> io::Result<()> {
+    let mut out = Output { inner: BufWriter::new(file), offset: 0 };
+    let mut sections = [(0u64, 0u64); 6];
+    out.write(&[0; HEADER_SIZE])?;
+
+    // The ids get compacted on the way so they can be used as indices in the docs table.
+    let ids = model.doc_ids();
+    let new_ids = ids.iter().enumerate().map(|(new_id, id)| (*id, new_id as u64)).collect::<HashMap<_, _>>();
+    let mut docs_table = Vec::with_capacity(ids.len()*DOC_ENTRY_SIZE);
+    let mut docs_strings = Vec::new();
+    let strings_offset = (ids.len()*DOC_ENTRY_SIZE) as u64;
+    let mut total_lengths = [0u64; Field::ALL.len()];
+    let mut language_counts = [0u64; Language::ALL.len()];
+    let mut texts_len = 0u64;
+    let mut spans_len = 0u64;
+    for id in &ids {
+        let doc = model.doc(*id).expect("doc_ids() returns only existing documents");
+        let path = doc.path.to_string_lossy();
+        let since_epoch = doc.last_modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        docs_table.extend((strings_offset + docs_strings.len() as u64).to_le_bytes());
+        docs_table.extend((path.len() as u32).to_le_bytes());
+        docs_table.extend((doc.lengths[Field::Body.index()] as u32).to_le_bytes());
+        docs_table.extend(since_epoch.as_secs().to_le_bytes());
+        docs_table.extend(since_epoch.subsec_nanos().to_le_bytes());
+        let language = doc.language.map(|language| language.index() as u32 + 1).unwrap_or(0);
+        docs_table.extend(language.to_le_bytes());
+        docs_table.extend(texts_len.to_le_bytes());
+        docs_table.extend((doc.text.len() as u64).to_le_bytes());
+        docs_table.extend((doc.lengths[Field::Title.index()] as u32).to_le_bytes());
+        docs_table.extend((doc.lengths[Field::Headings.index()] as u32).to_le_bytes());
+        docs_table.extend(spans_len.to_le_bytes());
+        docs_table.extend((doc.spans.0.len() as u64).to_le_bytes());
+        docs_table.extend((doc.lengths[Field::Code.index()] as u32).to_le_bytes());
+        docs_strings.extend(path.as_bytes());
+        texts_len += doc.text.len() as u64;
+        spans_len += doc.spans.0.len() as u64;
+        for (total, length) in total_lengths.iter_mut().zip(doc.lengths) {
+            *total += length as u64;
+        }
+        if let Some(language) = doc.language {
+            language_counts[language.index()] += 1;
+        }
+    }
+
+    // Meta
+    let start = out.offset;
+    let mut meta = Vec::new();
+    match model.scorer {
+        Scorer::Bm25 { k1, b } => {
+            meta.push(SCORER_BM25);
+            meta.extend(k1.to_le_bytes());
+            meta.extend(b.to_le_bytes());
+        }
+        Scorer::TfIdf => meta.push(SCORER_TFIDF),
+    }
+    for total in total_lengths {
+        meta.extend(total.to_le_bytes());
+    }
+    for count in language_counts {
+        meta.extend(count.to_le_bytes());
+    }
+    let analyzers = serde_json::to_vec(model.analyzers())?;
+    meta.extend((analyzers.len() as u64).to_le_bytes());
+    meta.extend(analyzers);
+    out.write(&meta)?;
+    sections[0] = (start, out.offset - start);
+
+    // Docs
+    let start = out.offset;
+    out.write(&docs_table)?;
+    out.write(&docs_strings)?;
+    sections[1] = (start, out.offset - start);
+
+    // Postings
+    let start = out.offset;
+    let mut terms = Vec::new();
+    let mut buffer = Vec::new();
+    for term in model.terms_from("") {
+        buffer.clear();
+        let mut doc_freq = 0;
+        let mut prev_id = 0;
+        for (id, positions) in model.term_postings(term).iter() {
+            let id = new_ids[&id];
+            write_varint(&mut buffer, id - prev_id);
+            prev_id = id;
+            let positions = positions.decode();
+            write_varint(&mut buffer, positions.len() as u64);
+            let mut prev_position = 0;
+            for position in positions.iter() {
+                write_varint(&mut buffer, (position - prev_position) as u64);
+                prev_position = *position;
+            }
+            doc_freq += 1;
+        }
+        if doc_freq == 0 {
+            continue;
+        }
+        terms.push((term, doc_freq, out.offset - start, buffer.len() as u64));
+        out.write(&buffer)?;
+    }
+    sections[3] = (start, out.offset - start);
+
+    // Terms
+    let start = out.offset;
+    let mut table = Vec::with_capacity(terms.len()*TERM_ENTRY_SIZE);
+    let mut strings = Vec::new();
+    let strings_offset = (terms.len()*TERM_ENTRY_SIZE) as u64;
+    for (term, doc_freq, postings_offset, postings_len) in &terms {
+        table.extend((strings_offset + strings.len() as u64).to_le_bytes());
+        table.extend((term.len() as u32).to_le_bytes());
+        table.extend((*doc_freq as u32).to_le_bytes());
+        table.extend(postings_offset.to_le_bytes());
+        table.extend(postings_len.to_le_bytes());
+        strings.extend(term.as_bytes());
+    }
+    out.write(&table)?;
+    out.write(&strings)?;
+    sections[2] = (start, out.offset - start);
+
+    // Texts
+    let start = out.offset;
+    for id in &ids {
+        out.write(model.doc(*id).expect("doc_ids() returns only existing documents").text.as_bytes())?;
+    }
+    sections[4] = (start, out.offset - start);
+
+    // Spans
+    let start = out.offset;
+    for id in &ids {
+        out.write(model.doc(*id).expect("doc_ids() returns only existing documents").spans.0)?;
+    }
+    sections[5] = (start, out.offset - start);
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend(MAGIC);
+    header.extend(VERSION.to_le_bytes());
+    header.extend(0u32.to_le_bytes());
+    header.extend((ids.len() as u64).to_le_bytes());
+    header.extend((terms.len() as u64).to_le_bytes());
+    for (offset, len) in sections {
+        header.extend(offset.to_le_bytes());
+        header.extend(len.to_le_bytes());
+    }
+    let mut file = out.inner.into_inner().map_err(|err| err.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.sync_all()
+}
+
+pub fn save_model(model: &Model, index_path: &Path) -> Result<(), ()> {
+    println!("Saving {index_path}...", index_path = index_path.display());
+
+    // Write into a temporary file next to the index first so a crash in the middle does not leave
+    // a broken index. The id of the process keeps its name apart from the files of the user.
+    let mut tmp_name = index_path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{pid}.tmp", pid = process::id()));
+    let tmp_path = index_path.with_file_name(tmp_name);
+    let tmp_file = File::create(&tmp_path).map_err(|err| {
+        eprintln!("ERROR: could not create index file {tmp_path}: {err}",
+                  tmp_path = tmp_path.display());
+    })?;
+
+    encode_model(model, tmp_file).map_err(|err| {
+        eprintln!("ERROR: could not write index into file {tmp_path}: {err}",
+                  tmp_path = tmp_path.display());
+        let _ = fs::remove_file(&tmp_path);
+    })?;
+
+    fs::rename(&tmp_path, index_path).map_err(|err| {
+        eprintln!("ERROR: could not move {tmp_path} to {index_path}: {err}",
+                  tmp_path = tmp_path.display(),
+                  index_path = index_path.display());
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::tests::{insert, sample_model, search, snippets};
+    use crate::scratch::ScratchPath;
+
+    #[test]
+    fn varint_round_trip() {
+        let values = [0, 1, 127, 128, 300, 16383, 16384, u32::MAX as u64, u64::MAX - 1, u64::MAX];
+        let mut bytes = Vec::new();
+        for value in values {
+            write_varint(&mut bytes, value);
+        }
+        let mut cursor = 0;
+        for value in values {
+            assert_eq!(read_varint(&bytes, &mut cursor), Some(value));
+        }
+        assert_eq!(cursor, bytes.len());
+        assert_eq!(read_varint(&bytes, &mut cursor), None);
+        // A varint that never ends
+        assert_eq!(read_varint(&[0xFF; 11], &mut 0), None);
+    }
+
+    #[test]
+    fn save_load_search() {
+        let file = ScratchPath::new("save-load-search.idx");
+        let model = sample_model();
+        save_model(&model, &file.0).unwrap();
+        let loaded = load_model(&file.0).unwrap();
+        assert_eq!(loaded.docs_count(), 3);
+        assert!(loaded.terms_from("").eq(model.terms_from("")));
+        for query in ["dog", "\"brown fox\"", "lazy -fox", "\"lazy dog\" OR cat"] {
+            assert_eq!(search(&loaded, query), search(&model, query), "{query}");
+            assert_eq!(snippets(&loaded, query), snippets(&model, query), "{query}");
+        }
+        assert_eq!(search(&loaded, "\"brown fox\""), ["/docs/fox.txt"]);
+        assert_eq!(snippets(&loaded, "\"brown fox\""), [["The quick [brown] [fox] jumps over the lazy dog"]]);
+        let doc = loaded.doc(loaded.doc_ids()[0]).unwrap();
+        assert_eq!(doc.path, Path::new("/docs/fox.txt"));
+        assert_eq!(doc.text, "The quick brown fox jumps over the lazy dog");
+        assert_eq!(doc.last_modified, UNIX_EPOCH + Duration::from_secs(1700000000));
+    }
+
+    #[test]
+    fn save_load_after_changes() {
+        let file = ScratchPath::new("save-load-after-changes.idx");
+        save_model(&sample_model(), &file.0).unwrap();
+        let mut model = load_model(&file.0).unwrap();
+        model.remove_document(Path::new("/docs/fox.txt"));
+        insert(&mut model, "/docs/dog.txt", "The dog chases the red fox");
+        assert_eq!(search(&model, "fox"), ["/docs/dog.txt", "/docs/cat.txt"]);
+        assert!(search(&model, "sleeps").is_empty());
+
+        save_model(&model, &file.0).unwrap();
+        let loaded = load_model(&file.0).unwrap();
+        assert_eq!(loaded.docs_count(), 2);
+        for query in ["fox", "sleeps", "\"red fox\"", "dog -cat"] {
+            assert_eq!(search(&loaded, query), search(&model, query), "{query}");
+        }
+        assert_eq!(search(&loaded, "\"red fox\""), ["/docs/dog.txt"]);
+    }
+
+    #[test]
+    fn mapped_postings() {
+        let file = ScratchPath::new("mapped-postings.idx");
+        save_model(&sample_model(), &file.0).unwrap();
+        let mut model = load_model(&file.0).unwrap();
+        insert(&mut model, "/docs/fox.txt", "A red fox");
+        // The reindexed document is skipped in the index file and found in the memory
+        let postings = model.term_postings("fox");
+        let decoded = postings.iter().map(|(id, positions)| (id, positions.decode().into_owned())).collect::<Vec<_>>();
+        assert_eq!(decoded, [(2, vec![6]), (3, vec![2])]);
+        assert_eq!(postings.doc_freq(), 2);
+        assert_eq!(model.term_postings("quick").doc_freq(), 0);
+        // The terms of the index file and the memory are merged in order
+        assert_eq!(model.terms_from("fox").take(2).collect::<Vec<_>>(), ["fox", "ignor"]);
+        assert_eq!(model.terms_from("r").take(2).collect::<Vec<_>>(), ["red", "sleep"]);
+        assert_eq!(model.terms_from("zzz").count(), 0);
+    }
+
+    #[test]
+    fn terms_count() {
+        let mut original = sample_model();
+        let titled = ParsedDoc { title: "Zebra".to_string(), ..Default::default() };
+        original.insert_document("/docs/zebra.txt".into(), UNIX_EPOCH, AnalyzedDoc::new(titled, None, &FieldAnalyzers::default()));
+        // Only the terms of the body count
+        let body_terms = sample_model().terms_from("").count();
+        assert_eq!(original.terms_count(), body_terms);
+        let file = ScratchPath::new("terms-count.idx");
+        save_model(&original, &file.0).unwrap();
+        let mut model = load_model(&file.0).unwrap();
+        assert_eq!(model.terms_count(), body_terms);
+
+        // The terms left without the documents don't count either
+        model.remove_document(Path::new("/docs/fox.txt"));
+        insert(&mut model, "/docs/dog.txt", "The dog chases the red fox");
+        let mut rebuilt = Model::default();
+        insert(&mut rebuilt, "/docs/cat.txt", "Cats ignore the dogs and the foxes");
+        insert(&mut rebuilt, "/docs/dog.txt", "The dog chases the red fox");
+        assert_eq!(model.terms_count(), rebuilt.terms_from("").count());
+    }
+
+    #[test]
+    fn save_snapshot() {
+        let file = ScratchPath::new("save-snapshot.idx");
+        save_model(&sample_model(), &file.0).unwrap();
+        let mut model = load_model(&file.0).unwrap();
+        model.remove_document(Path::new("/docs/fox.txt"));
+        insert(&mut model, "/docs/dog.txt", "The dog chases the red fox");
+        let snapshot = model.snapshot();
+        assert_eq!(snapshot.changes(), model.changes());
+
+        save_model(&snapshot, &file.0).unwrap();
+        let loaded = load_model(&file.0).unwrap();
+        assert_eq!(loaded.docs_count(), 2);
+        for query in ["fox", "sleeps", "\"red fox\"", "dog -cat"] {
+            assert_eq!(search(&loaded, query), search(&model, query), "{query}");
+            assert_eq!(snippets(&loaded, query), snippets(&model, query), "{query}");
+        }
+        // The documents shared with the snapshot are copied on the change
+        insert(&mut model, "/docs/cat.txt", "Cats chase nothing");
+        assert_ne!(snapshot.changes(), model.changes());
+        assert_eq!(search(&snapshot, "chase"), ["/docs/dog.txt"]);
+        assert_eq!(search(&model, "chase"), ["/docs/cat.txt", "/docs/dog.txt"]);
+    }
+
+    #[test]
+    fn keeps_the_files_next_to_it() {
+        let dir = ScratchPath::dir("keeps-the-files-next-to-it", &[("index.tmp", "mine")]);
+        let index_path = dir.0.join("index.idx");
+        save_model(&sample_model(), &index_path).unwrap();
+        assert_eq!(fs::read_to_string(dir.0.join("index.tmp")).unwrap(), "mine");
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 2);
+        assert_eq!(load_model(&index_path).unwrap().docs_count(), 3);
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        let file = ScratchPath::new("rejects-broken-files.idx");
+        save_model(&sample_model(), &file.0).unwrap();
+        let bytes = fs::read(&file.0).unwrap();
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        let mut wrong_version = bytes.clone();
+        wrong_version[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let broken = [
+            &bytes[..HEADER_SIZE / 2],
+            &bytes[..bytes.len() - 1],
+            &wrong_magic[..],
+            &wrong_version[..],
+        ];
+        for broken in broken {
+            fs::write(&file.0, broken).unwrap();
+            assert!(load_model(&file.0).is_err());
+        }
+    }
+}
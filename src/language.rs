@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use super::snowball::SnowballEnv;
+use super::snowball::algorithms::*;
+
+/// How much of the text is enough to tell the language. The rest of a big document would only
+/// slow the detection down.
+const DETECTION_SAMPLE_LEN: usize = 4096;
+
+/// The languages the Snowball stemmers are available for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    English,
+    German,
+    French,
+    Spanish,
+    Russian,
+    Portuguese,
+}
+
+impl Language {
+    pub const ALL: [Language; 6] = [
+        Language::English,
+        Language::German,
+        Language::French,
+        Language::Spanish,
+        Language::Russian,
+        Language::Portuguese,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "english",
+            Language::German => "german",
+            Language::French => "french",
+            Language::Spanish => "spanish",
+            Language::Russian => "russian",
+            Language::Portuguese => "portuguese",
+        }
+    }
+
+    /// ISO 639-1 code of the language.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::French => "fr",
+            Language::Spanish => "es",
+            Language::Russian => "ru",
+            Language::Portuguese => "pt",
+        }
+    }
+
+    /// Accepts the name as well as the code of the language.
+    pub fn from_name(name: &str) -> Option<Language> {
+        Language::ALL.into_iter().find(|language| language.name() == name || language.code() == name)
+    }
+
+    /// The names of all the languages for the error messages.
+    pub fn names() -> String {
+        Language::ALL.map(Language::name).join(", ")
+    }
+
+    pub fn stemmer(self) -> fn(&mut SnowballEnv) -> bool {
+        match self {
+            Language::English => english_stemmer::stem,
+            Language::German => german_stemmer::stem,
+            Language::French => french_stemmer::stem,
+            Language::Spanish => spanish_stemmer::stem,
+            Language::Russian => russian_stemmer::stem,
+            Language::Portuguese => portuguese_stemmer::stem,
+        }
+    }
+}
+
+/// Identifies the language of the text by its trigrams. Returns `None` if the text is too short
+/// to tell or it is in a language there is no stemmer for.
+pub fn detect(text: &str) -> Option<Language> {
+    let end = text.char_indices().nth(DETECTION_SAMPLE_LEN).map(|(i, _)| i).unwrap_or(text.len());
+    let info = whatlang::detect(&text[..end])?;
+    if !info.is_reliable() {
+        return None;
+    }
+    match info.lang() {
+        whatlang::Lang::Eng => Some(Language::English),
+        whatlang::Lang::Deu => Some(Language::German),
+        whatlang::Lang::Fra => Some(Language::French),
+        whatlang::Lang::Spa => Some(Language::Spanish),
+        whatlang::Lang::Rus => Some(Language::Russian),
+        whatlang::Lang::Por => Some(Language::Portuguese),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_languages() {
+        let cases = [
+            ("The quick brown fox jumps over the lazy dog while the farmer is watching it from the window", Some(Language::English)),
+            ("Der schnelle braune Fuchs springt über den faulen Hund, während der Bauer ihn aus dem Fenster beobachtet", Some(Language::German)),
+            ("Le renard brun rapide saute par-dessus le chien paresseux pendant que le fermier le regarde par la fenêtre", Some(Language::French)),
+            ("El rápido zorro marrón salta sobre el perro perezoso mientras el granjero lo mira desde la ventana", Some(Language::Spanish)),
+            ("Быстрая коричневая лиса прыгает через ленивую собаку, пока фермер смотрит на неё из окна", Some(Language::Russian)),
+            ("A rápida raposa marrom pula sobre o cão preguiçoso enquanto o fazendeiro a observa da janela", Some(Language::Portuguese)),
+            // There is no stemmer for Italian
+            ("La veloce volpe marrone salta sopra il cane pigro mentre il contadino la guarda dalla finestra", None),
+            // Too short to tell
+            ("ok", None),
+            ("", None),
+        ];
+        for (text, language) in cases {
+            assert_eq!(detect(text), language, "{text}");
+        }
+    }
+}
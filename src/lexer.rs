@@ -1,58 +1,85 @@
-pub struct Lexer<'a> {
-    content: &'a [char],
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Splits the text into tokens on the Unicode word boundaries (UAX #29). The tokens are returned
+/// as is, turning them into terms is up to the filters of the analyzer.
+pub struct Lexer<'t> {
+    content: &'t str,
+    /// Byte offset of the rest of the content.
+    cursor: usize,
+    /// Produce the tokens out of the punctuation and the symbols too.
+    keep_symbols: bool,
 }
 
-impl<'a> Lexer<'a> {
-    pub fn new(content: &'a [char]) -> Self {
-        Self { content }
+impl<'t> Lexer<'t> {
+    pub fn new(content: &'t str, keep_symbols: bool) -> Self {
+        Self { content, cursor: 0, keep_symbols }
     }
 
-    fn trim_left(&mut self) {
-        while !self.content.is_empty() && self.content[0].is_whitespace() {
-            self.content = &self.content[1..];
+    /// Chops off the text up to the next word boundary.
+    fn chop_segment(&mut self) -> Option<(&'t str, Range<usize>)> {
+        let rest = &self.content[self.cursor..];
+        let mut segment = rest.split_word_bounds().next()?;
+        // UAX #29 keeps the words like `foo:bar` together. Split them like `foo::bar`, so the
+        // parts can be found on their own
+        if let Some(colon) = segment.find(':') {
+            segment = &segment[..colon.max(1)];
         }
+        let start = self.cursor;
+        self.cursor += segment.len();
+        Some((segment, start..self.cursor))
     }
 
-    fn chop(&mut self, n: usize) -> &'a [char] {
-        let token = &self.content[0..n];
-        self.content = &self.content[n..];
-        token
-    }
-
-    fn chop_while<P>(&mut self, mut predicate: P) -> &'a [char] where P: FnMut(&char) -> bool {
-        let mut n = 0;
-        while n < self.content.len() && predicate(&self.content[n]) {
-            n += 1;
+    /// Returns the token along with the range of bytes it was produced from.
+    pub fn next_token_with_span(&mut self) -> Option<(&'t str, Range<usize>)> {
+        loop {
+            let (segment, span) = self.chop_segment()?;
+            // Never a token, so they can mark the terms of the fields (see `Field::term_key`)
+            if segment.chars().all(|c| c.is_whitespace() || c.is_control()) {
+                continue;
+            }
+            let is_word = segment.chars().any(char::is_alphanumeric);
+            if !is_word && !self.keep_symbols {
+                continue;
+            }
+            return Some((segment, span));
         }
-        self.chop(n)
     }
+}
 
-    pub fn next_token(&mut self) -> Option<String> {
-        self.trim_left();
-        if self.content.is_empty() {
-            return None
-        }
+impl<'t> Iterator for Lexer<'t> {
+    type Item = (&'t str, Range<usize>);
 
-        if self.content[0].is_numeric() {
-            return Some(self.chop_while(|x| x.is_numeric()).iter().collect());
-        }
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token_with_span()
+    }
+}
 
-        if self.content[0].is_alphabetic() {
-            let term = self.chop_while(|x| x.is_alphanumeric()).iter().map(|x| x.to_ascii_lowercase()).collect::<String>();
-            let mut env = crate::snowball::SnowballEnv::create(&term);
-            crate::snowball::algorithms::english_stemmer::stem(&mut env);
-            let stemmed_term = env.get_current().to_string();
-            return Some(stemmed_term);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        return Some(self.chop(1).iter().collect());
+    fn tokens(content: &str, keep_symbols: bool) -> Vec<&str> {
+        Lexer::new(content, keep_symbols).map(|(token, _)| token).collect()
     }
-}
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = String;
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(tokens("Hello, world! It's 3.14", false), ["Hello", "world", "It's", "3.14"]);
+        assert_eq!(tokens("a + b", true), ["a", "+", "b"]);
+        // The ideographs are words of their own, the kana stick together
+        assert_eq!(tokens("東京タワーへ", false), ["東", "京", "タワー", "へ"]);
+        assert_eq!(tokens("foo:bar std::fs", false), ["foo", "bar", "std", "fs"]);
+        assert_eq!(tokens("foo:bar", true), ["foo", ":", "bar"]);
+        assert_eq!(tokens("\u{0}title\u{1}", true), ["title"]);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next_token()
+    #[test]
+    fn spans() {
+        let content = "  naïve café:au lait ";
+        for (token, span) in Lexer::new(content, false) {
+            assert_eq!(&content[span], token);
+        }
+        assert_eq!(Lexer::new(content, false).map(|(_, span)| span).collect::<Vec<_>>(), [2..8, 9..14, 15..17, 18..22]);
     }
 }
@@ -1,19 +1,40 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::path::{Path};
+use std::path::{Path, PathBuf};
 use xml::reader::{XmlEvent, EventReader};
 use xml::common::{Position, TextPosition};
 use std::env;
 use std::result::Result;
 use std::process::ExitCode;
 use std::str;
-use std::io::{BufReader, BufWriter};
+use std::io::{self, BufReader, IsTerminal, Write};
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, SyncSender};
 use std::thread;
+use std::time::SystemTime;
+use serde::Serialize;
 
 mod model;
 use model::*;
 mod server;
 mod lexer;
+mod analyzer;
+use analyzer::{Analyzer, FieldAnalyzers};
+mod language;
+use language::Language;
+mod query;
+mod index_file;
+#[cfg(test)]
+mod scratch;
+mod snippet;
+mod repl;
+mod watcher;
+mod file_filter;
+mod html;
+mod html_entities;
+mod markdown;
+use file_filter::FileFilter;
+use server::{SearchHit, DEFAULT_SEARCH_LIMIT};
 pub mod snowball;
 
 fn parse_entire_txt_file(file_path: &Path) -> Result<String, ()> {
@@ -22,7 +43,7 @@ fn parse_entire_txt_file(file_path: &Path) -> Result<String, ()> {
     })
 }
 
-fn parse_entire_pdf_file(file_path: &Path) -> Result<String, ()> {
+fn parse_entire_pdf_file(file_path: &Path) -> Result<ParsedDoc, ()> {
     use poppler::Document;
     use std::io::Read;
 
@@ -38,26 +59,33 @@ fn parse_entire_pdf_file(file_path: &Path) -> Result<String, ()> {
                   file_path = file_path.display());
     })?;
 
-    let mut result = String::new();
+    let mut result = ParsedDoc::default();
+    if let Some(title) = pdf.title() {
+        result.title = title.as_str().to_string();
+    }
 
     let n = pdf.n_pages();
     for i in 0..n {
-        let page = pdf.page(i).expect(&format!("{i} is within the bounds of the range of the page"));
+        let page = pdf.page(i).unwrap_or_else(|| panic!("{i} is within the bounds of the range of the page"));
         if let Some(content) = page.text() {
-            result.push_str(content.as_str());
-            result.push(' ');
+            result.body.push_str(content.as_str());
+            result.body.push(' ');
         }
     }
 
     Ok(result)
 }
 
-fn parse_entire_xml_file(file_path: &Path) -> Result<String, ()> {
+/// The first `<title>` is the title of the document (the one in `<head>` of XHTML). The rest of
+/// them (sections of DocBook and alike) and `<h1>`..`<h6>` are the headings.
+fn parse_entire_xml_file(file_path: &Path) -> Result<ParsedDoc, ()> {
     let file = File::open(file_path).map_err(|err| {
         eprintln!("ERROR: could not open file {file_path}: {err}", file_path = file_path.display());
     })?;
     let er = EventReader::new(BufReader::new(file));
-    let mut content = String::new();
+    let mut doc = ParsedDoc::default();
+    let mut title = None;
+    let mut heading = None;
     for event in er.into_iter() {
         let event = event.map_err(|err| {
             let TextPosition {row, column} = err.position();
@@ -65,114 +93,608 @@ fn parse_entire_xml_file(file_path: &Path) -> Result<String, ()> {
             eprintln!("{file_path}:{row}:{column}: ERROR: {msg}", file_path = file_path.display());
         })?;
 
-        if let XmlEvent::Characters(text) = event {
-            content.push_str(&text);
-            content.push(' ');
+        match event {
+            XmlEvent::StartElement { name, .. } => match name.local_name.to_lowercase().as_str() {
+                "title" if doc.title.is_empty() && title.is_none() => title = Some(String::new()),
+                "title" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => heading = Some(String::new()),
+                _ => {}
+            }
+            XmlEvent::EndElement { name } => match name.local_name.to_lowercase().as_str() {
+                "title" if title.is_some() => doc.title = title.take().unwrap_or_default(),
+                "title" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => doc.headings.extend(heading.take()),
+                _ => {}
+            }
+            XmlEvent::Characters(text) => {
+                if let Some(title) = &mut title {
+                    title.push_str(&text);
+                    title.push(' ');
+                    continue;
+                }
+                doc.body.push_str(&text);
+                doc.body.push(' ');
+                if let Some(heading) = &mut heading {
+                    heading.push_str(&text);
+                    heading.push(' ');
+                }
+            }
+            _ => {}
         }
     }
-    Ok(content)
+    Ok(doc)
 }
 
-fn parse_entire_file_by_extension(file_path: &Path) -> Result<String, ()> {
-    let extension = file_path.extension().ok_or_else(|| {
-        eprintln!("ERROR: can't detect file type of {file_path} without extension",
-                  file_path = file_path.display());
-    })?.to_string_lossy();
+fn parse_entire_md_file(file_path: &Path) -> Result<ParsedDoc, ()> {
+    parse_entire_txt_file(file_path).map(|source| markdown::parse(&source))
+}
+
+fn parse_entire_html_file(file_path: &Path) -> Result<ParsedDoc, ()> {
+    let source = fs::read(file_path).map_err(|err| {
+        eprintln!("ERROR: could not open file {file_path}: {err}", file_path = file_path.display());
+    })?;
+    // The pages in legacy encodings still have plenty of ASCII text to index
+    Ok(html::parse(&String::from_utf8_lossy(&source)))
+}
+
+type FileParser = fn(&Path) -> Result<ParsedDoc, ()>;
+
+fn parser_by_extension(file_path: &Path) -> Option<FileParser> {
+    let extension = file_path.extension()?.to_string_lossy();
     match extension.as_ref() {
-        "xhtml" | "xml" => parse_entire_xml_file(file_path),
-        // TODO: specialized parser for markdown files
-        "txt" | "md" => parse_entire_txt_file(file_path),
-        "pdf" => parse_entire_pdf_file(file_path),
-        _ => {
-            eprintln!("ERROR: can't detect file type of {file_path}: unsupported extension {extension}",
-                      file_path = file_path.display(),
-                      extension = extension);
-            Err(())
-        }
+        "xhtml" | "xml" => Some(parse_entire_xml_file),
+        "txt" => Some(|file_path| parse_entire_txt_file(file_path).map(ParsedDoc::from_body)),
+        "md" => Some(parse_entire_md_file),
+        "html" | "htm" => Some(parse_entire_html_file),
+        "pdf" => Some(parse_entire_pdf_file),
+        _ => None,
     }
 }
 
-fn save_model_as_json(model: &Model, index_path: &Path) -> Result<(), ()> {
-    println!("Saving {index_path}...", index_path = index_path.display());
-
-    let index_file = File::create(index_path).map_err(|err| {
-        eprintln!("ERROR: could not create index file {index_path}: {err}",
-                  index_path = index_path.display());
+fn parse_entire_file_by_extension(file_path: &Path) -> Result<ParsedDoc, ()> {
+    let parser = parser_by_extension(file_path).ok_or_else(|| {
+        eprintln!("ERROR: can't detect file type of {file_path}: unsupported extension",
+                  file_path = file_path.display());
     })?;
+    parser(file_path)
+}
 
-    serde_json::to_writer(BufWriter::new(index_file), &model).map_err(|err| {
-        eprintln!("ERROR: could not serialize index into file {index_path}: {err}",
-                  index_path = index_path.display());
-    })?;
+#[derive(Default)]
+struct IndexingStats {
+    indexed: usize,
+    /// Up to date or unsupported files.
+    skipped: usize,
+    failed: usize,
+    /// Documents of the files that are gone or ignored now.
+    removed: usize,
+}
 
-    Ok(())
+impl IndexingStats {
+    fn model_changed(&self) -> bool {
+        self.indexed > 0 || self.removed > 0
+    }
 }
 
-fn add_folder_to_model(dir_path: &Path, model: Arc<Mutex<Model>>, processed: &mut usize) -> Result<(), ()> {
-    let dir = fs::read_dir(dir_path).map_err(|err| {
-        eprintln!("ERROR: could not open directory {dir_path} for indexing: {err}",
-                  dir_path = dir_path.display());
-    })?;
+/// Removes the documents of the files of the folder that were deleted or became ignored since
+/// they were indexed. The filesystem is checked without holding the lock of the model.
+fn remove_stale_documents(filter: &FileFilter, dir_path: &Path, model: &Mutex<Model>, stats: &mut IndexingStats) {
+    let paths = {
+        let model = model.lock().unwrap();
+        model.doc_ids().into_iter()
+            .filter_map(|id| Some(model.doc(id)?.path.to_path_buf()))
+            .filter(|file_path| file_path.starts_with(dir_path))
+            .collect::<Vec<_>>()
+    };
+    let stale = paths.into_iter().filter(|file_path| {
+        // Keep the document if we can't tell for sure, it is going to be checked again on the next run
+        let exists = file_path.try_exists().unwrap_or(true);
+        !exists || filter.is_ignored(file_path, false) || parser_by_extension(file_path).is_none()
+    }).collect::<Vec<_>>();
+    if stale.is_empty() {
+        return;
+    }
 
-    'next_file: for file in dir {
-        let file = file.map_err(|err| {
-            eprintln!("ERROR: could not read next file in directory {dir_path} during indexing: {err}",
-                      dir_path = dir_path.display());
-        })?;
+    let mut model = model.lock().unwrap();
+    for file_path in stale {
+        println!("Removing {:?}...", &file_path);
+        model.remove_document(&file_path);
+        stats.removed += 1;
+    }
+}
 
-        let file_path = file.path();
+/// Brings the model up to date with the folder.
+fn index_folder(filter: &FileFilter, model: Arc<Mutex<Model>>, stats: &mut IndexingStats) -> Result<(), ()> {
+    remove_stale_documents(filter, filter.root(), &model, stats);
+    add_folder_to_model(filter, filter.root(), model, stats)
+}
 
-        let dot_file = file_path
-            .file_name()
-            .and_then(|s| s.to_str())
-            .map(|s| s.starts_with("."))
-            .unwrap_or(false);
+/// Indexes a single file. The model is locked only to check whether the file has changed and to
+/// insert the parsed document, so the searches are not blocked by the parsing.
+fn add_file_to_model(file_path: PathBuf, last_modified: SystemTime, model: &Mutex<Model>, stats: &mut IndexingStats) {
+    let analyzers = {
+        let mut model = model.lock().unwrap();
+        if parser_by_extension(&file_path).is_none() || !model.requires_reindexing(&file_path, last_modified) {
+            stats.skipped += 1;
+            return;
+        }
+        model.analyzers().clone()
+    };
 
-        if dot_file {
-            continue 'next_file;
+    match parse_file(&file_path, &analyzers) {
+        Ok(doc) => {
+            model.lock().unwrap().insert_document(file_path, last_modified, doc);
+            stats.indexed += 1;
         }
+        Err(()) => stats.failed += 1,
+    }
+}
 
-        let file_type = file.file_type().map_err(|err| {
-            eprintln!("ERROR: could not determine type of file {file_path}: {err}",
-                      file_path = file_path.display());
-        })?;
-        let last_modified = file.metadata().map_err(|err| {
-            eprintln!("ERROR: could not get the metadata of file {file_path}: {err}",
-                      file_path = file_path.display());
-        })?.modified().map_err(|err| {
-            eprintln!("ERROR: could not get the last modification date of file {file_path}: {err}",
-                      file_path = file_path.display())
-        })?;
+/// The modification times of the indexed files of the folder, so the walk does not have to lock
+/// the model for every file.
+fn indexed_files(model: &Model, dir_path: &Path) -> HashMap<PathBuf, SystemTime> {
+    model.doc_ids().into_iter()
+        .filter_map(|id| model.doc(id))
+        .filter(|doc| doc.path.starts_with(dir_path))
+        .map(|doc| (doc.path.to_path_buf(), doc.last_modified))
+        .collect()
+}
 
-        if file_type.is_dir() {
-            add_folder_to_model(&file_path, Arc::clone(&model), processed)?;
-            continue 'next_file;
-        }
+/// Finds the files of the folder that need to be (re)indexed and sends them to the workers.
+fn walk_folder(filter: &FileFilter, dir_path: &Path, indexed: &HashMap<PathBuf, SystemTime>, files: &SyncSender<(PathBuf, SystemTime)>, stats: &mut IndexingStats) -> Result<(), ()> {
+    for entry in filter.walk(dir_path) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("WARNING: {err}");
+                continue;
+            }
+        };
 
         // TODO: how does this work with symlinks?
+        if !entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false) {
+            continue;
+        }
 
-        let mut model = model.lock().unwrap();
-        if model.requires_reindexing(&file_path, last_modified) {
-            println!("Indexing {:?}...", &file_path);
-
-            let content = match parse_entire_file_by_extension(&file_path) {
-                Ok(content) => content.chars().collect::<Vec<_>>(),
-                // TODO: still add the skipped files to the model to prevent their reindexing in the future
-                Err(()) => continue 'next_file,
-            };
+        let file_path = entry.into_path();
+        let last_modified = match fs::metadata(&file_path).and_then(|metadata| metadata.modified()) {
+            Ok(last_modified) => last_modified,
+            Err(err) => {
+                eprintln!("ERROR: could not get the last modification date of file {file_path}: {err}",
+                          file_path = file_path.display());
+                stats.failed += 1;
+                continue;
+            }
+        };
 
-            model.add_document(file_path, last_modified, &content);
-            *processed += 1;
+        let up_to_date = indexed.get(&file_path).is_some_and(|indexed| *indexed >= last_modified);
+        if parser_by_extension(&file_path).is_none() || up_to_date {
+            stats.skipped += 1;
+            continue;
         }
+
+        files.send((file_path, last_modified)).map_err(|_| {
+            eprintln!("ERROR: indexing workers have stopped");
+        })?;
     }
 
     Ok(())
 }
 
+/// How many parsed documents are merged into the model under a single lock.
+const MERGE_BATCH_SIZE: usize = 64;
+
+struct ParsedFile {
+    file_path: PathBuf,
+    last_modified: SystemTime,
+    doc: Result<AnalyzedDoc, ()>,
+}
+
+fn parse_file(file_path: &Path, analyzers: &FieldAnalyzers) -> Result<AnalyzedDoc, ()> {
+    println!("Indexing {:?}...", file_path);
+    let doc = parse_entire_file_by_extension(file_path)?;
+    let language = language::detect(if doc.body.is_empty() { &doc.title } else { &doc.body });
+    Ok(AnalyzedDoc::new(doc, language, analyzers))
+}
+
+/// Indexes the folder with a pool of workers. The walker feeds the workers with the files, the
+/// workers parse and tokenize them, and the results are merged into the model in batches, so the
+/// model is locked only for short periods of time.
+fn add_folder_to_model(filter: &FileFilter, dir_path: &Path, model: Arc<Mutex<Model>>, stats: &mut IndexingStats) -> Result<(), ()> {
+    let jobs = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    // Bounded, so the walker does not run too far ahead of the workers
+    let (files_sender, files) = mpsc::sync_channel::<(PathBuf, SystemTime)>(jobs*4);
+    // Owned by the workers only, so the walker stops once all of them are gone
+    let files = Arc::new(Mutex::new(files));
+    let (parsed_sender, parsed) = mpsc::channel::<ParsedFile>();
+    let model: &Mutex<Model> = &model;
+    let (analyzers, indexed) = {
+        let model = model.lock().unwrap();
+        (model.analyzers().clone(), indexed_files(&model, dir_path))
+    };
+
+    thread::scope(|scope| {
+        let mut workers = Vec::with_capacity(jobs);
+        for _ in 0..jobs {
+            let files = Arc::clone(&files);
+            let analyzers = &analyzers;
+            let parsed_sender = parsed_sender.clone();
+            workers.push(scope.spawn(move || {
+                loop {
+                    // The lock of the queue must be released before parsing
+                    let next = files.lock().unwrap().recv();
+                    let Ok((file_path, last_modified)) = next else {
+                        break;
+                    };
+                    let doc = parse_file(&file_path, analyzers);
+                    if parsed_sender.send(ParsedFile { file_path, last_modified, doc }).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        drop(files);
+        drop(parsed_sender);
+
+        let indexed = &indexed;
+        let walker = scope.spawn(move || {
+            let mut stats = IndexingStats::default();
+            walk_folder(filter, dir_path, indexed, &files_sender, &mut stats).map(|()| stats)
+        });
+
+        let mut batch = Vec::new();
+        while let Ok(file) = parsed.recv() {
+            batch.push(file);
+            while batch.len() < MERGE_BATCH_SIZE {
+                match parsed.try_recv() {
+                    Ok(file) => batch.push(file),
+                    Err(_) => break,
+                }
+            }
+
+            let mut model = model.lock().unwrap();
+            for ParsedFile { file_path, last_modified, doc } in batch.drain(..) {
+                match doc {
+                    Ok(doc) => {
+                        model.insert_document(file_path, last_modified, doc);
+                        stats.indexed += 1;
+                    }
+                    // TODO: still add the failed files to the model to prevent their reindexing in the future
+                    Err(()) => stats.failed += 1,
+                }
+            }
+        }
+
+        // The panics of the workers are reported by the panic hook, the files they were parsing are
+        // just left out
+        let mut result = Ok(());
+        for worker in workers {
+            if worker.join().is_err() {
+                eprintln!("ERROR: an indexing worker has crashed");
+                result = Err(());
+            }
+        }
+        let walker_stats = walker.join().expect("walker does not panic")?;
+        stats.skipped += walker_stats.skipped;
+        stats.failed += walker_stats.failed;
+        result
+    })
+}
+
 fn usage(program: &str) {
     eprintln!("Usage: {program} [SUBCOMMAND] [OPTIONS]");
     eprintln!("Subcommands:");
     eprintln!("    serve <folder> [address]       start local HTTP server with Web Interface");
+    eprintln!("    index <folder>                 index the folder without serving it");
+    eprintln!("        --output <path>            where to write the index (default: <folder>/.seroost.idx)");
+    eprintln!("    search <index-or-folder> <query>  search the index and print the results");
+    eprintln!("        --limit <number>           maximum amount of the results (default: {DEFAULT_SEARCH_LIMIT})");
+    eprintln!("        --json                     print the results as JSON");
+    eprintln!("    repl <folder>                  explore the index of the folder interactively");
+    eprintln!("        --reindex                  bring the index up to date with the folder first");
+    eprintln!("Index options (serve, index):");
+    eprintln!("    --scorer <bm25|tfidf>          ranking function of the index (default: bm25)");
+    eprintln!("    --k1 <number>                  BM25 term frequency saturation (default: {k1})", k1 = Scorer::DEFAULT_K1);
+    eprintln!("    --b <number>                   BM25 document length normalization (default: {b})", b = Scorer::DEFAULT_B);
+    eprintln!("    --analyzer [<field>=]<spec>    how the text of the field (body, title, headings, code) or all of them is");
+    eprintln!("                                   turned into terms (default: {default}). The spec is", default = Analyzer::default());
+    eprintln!("                                   a tokenizer followed by the token filters, separated by commas:");
+    eprintln!("        words                      the words on the Unicode word boundaries");
+    eprintln!("        words-and-symbols          the words plus the punctuation and the symbols");
+    eprintln!("        lowercase                  fold the case, so `Café` and `café` are the same term");
+    eprintln!("        ascii-folding              strip the diacritics, so `café` and `cafe` are the same term");
+    eprintln!("        stem[:<language>]          reduce the words to their stems (default: english). The language");
+    eprintln!("                                   is detected for every document, this one is used if it can't be.");
+    eprintln!("        stop-words[:<file>]        drop the words of the list (default: english). A file has a word");
+    eprintln!("                                   per line.");
+    eprintln!("        synonyms:<file>            add the synonyms of the words. A file has a comma separated");
+    eprintln!("                                   group of the equivalent words per line.");
+    eprintln!("        ngrams:<min>-<max>         add the parts of the words of the sizes, so they match partially");
+    eprintln!("    --language <language>          the language of the stemmers of all the fields for the documents");
+    eprintln!("                                   of the undetected languages: {names}", names = Language::names());
+    eprintln!("    Changing the analyzers or the language reindexes the folder from scratch.");
+    eprintln!("Filter options (serve, index, repl --reindex):");
+    eprintln!("    --exclude <glob>               do not index the files that match the glob");
+    eprintln!("    --include <glob>               index only the files that match the glob");
+    eprintln!("    The files ignored by .gitignore, .ignore and .seroostignore are not indexed either.");
+}
+
+#[derive(Default)]
+struct FilterOptions {
+    includes: Vec<String>,
+    excludes: Vec<String>,
+}
+
+impl FilterOptions {
+    /// Consumes the flag and its value if the flag is one of the filter options.
+    fn parse_flag(&mut self, flag: &str, args: &mut impl Iterator<Item = String>) -> Result<bool, ()> {
+        let globs = match flag {
+            "--include" => &mut self.includes,
+            "--exclude" => &mut self.excludes,
+            _ => return Ok(false),
+        };
+        globs.push(args.next().ok_or_else(|| {
+            eprintln!("ERROR: no value is provided for flag {flag}");
+        })?);
+        Ok(true)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.includes.is_empty() && self.excludes.is_empty()
+    }
+
+    fn build(&self, dir_path: &Path) -> Result<FileFilter, ()> {
+        FileFilter::new(dir_path, &self.includes, &self.excludes)
+    }
+}
+
+#[derive(Default)]
+struct ScorerOptions {
+    name: Option<String>,
+    k1: Option<f32>,
+    b: Option<f32>,
+}
+
+impl ScorerOptions {
+    /// Consumes the flag and its value if the flag is one of the scorer options.
+    fn parse_flag(&mut self, flag: &str, args: &mut impl Iterator<Item = String>) -> Result<bool, ()> {
+        match flag {
+            "--scorer" => {
+                self.name = Some(args.next().ok_or_else(|| {
+                    eprintln!("ERROR: no value is provided for flag {flag}");
+                })?);
+            }
+            "--k1" => {
+                let k1 = parse_number_flag(flag, args.next())?;
+                if !k1.is_finite() || k1 < 0.0 {
+                    eprintln!("ERROR: {k1} is not a valid value for flag {flag}: expected a non-negative number");
+                    return Err(());
+                }
+                self.k1 = Some(k1);
+            }
+            "--b" => {
+                let b = parse_number_flag(flag, args.next())?;
+                if !(0.0..=1.0).contains(&b) {
+                    eprintln!("ERROR: {b} is not a valid value for flag {flag}: expected a number from 0 to 1");
+                    return Err(());
+                }
+                self.b = Some(b);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.name.is_none() && self.k1.is_none() && self.b.is_none()
+    }
+
+    /// Updates the scorer of the model. Returns whether it has changed.
+    fn apply_to(&self, model: &mut Model) -> Result<bool, ()> {
+        let scorer = self.apply(model.scorer)?;
+        if !self.is_empty() {
+            println!("INFO: using scorer {scorer:?}");
+        }
+        let changed = scorer != model.scorer;
+        model.scorer = scorer;
+        Ok(changed)
+    }
+
+    /// Applies the options on top of the scorer that is already stored in the index.
+    fn apply(&self, current: Scorer) -> Result<Scorer, ()> {
+        let (current_k1, current_b) = match current {
+            Scorer::Bm25 { k1, b } => (k1, b),
+            Scorer::TfIdf => (Scorer::DEFAULT_K1, Scorer::DEFAULT_B),
+        };
+        let bm25 = Scorer::Bm25 {
+            k1: self.k1.unwrap_or(current_k1),
+            b: self.b.unwrap_or(current_b),
+        };
+        match self.name.as_deref() {
+            Some("bm25") => Ok(bm25),
+            Some("tfidf") => {
+                if self.k1.is_some() || self.b.is_some() {
+                    eprintln!("ERROR: --k1 and --b are only applicable to the bm25 scorer");
+                    return Err(());
+                }
+                Ok(Scorer::TfIdf)
+            }
+            Some(name) => {
+                eprintln!("ERROR: unknown scorer {name}. Available scorers: bm25, tfidf");
+                Err(())
+            }
+            None if self.k1.is_some() || self.b.is_some() => Ok(bm25),
+            None => Ok(current),
+        }
+    }
+}
+
+#[derive(Default)]
+struct AnalyzerFlags {
+    /// The specs in the order of the flags. `None` means all the fields.
+    specs: Vec<(Option<Field>, String)>,
+    language: Option<Language>,
+}
+
+impl AnalyzerFlags {
+    /// Consumes the flag and its value if it is `--analyzer` or `--language`.
+    fn parse_flag(&mut self, flag: &str, args: &mut impl Iterator<Item = String>) -> Result<bool, ()> {
+        if flag != "--analyzer" && flag != "--language" {
+            return Ok(false);
+        }
+        let value = args.next().ok_or_else(|| {
+            eprintln!("ERROR: no value is provided for flag {flag}");
+        })?;
+        if flag == "--language" {
+            let language = Language::from_name(&value).ok_or_else(|| {
+                eprintln!("ERROR: unknown language {value}. Available languages: {names}", names = Language::names());
+            })?;
+            self.language = Some(language);
+            return Ok(true);
+        }
+        match value.split_once('=') {
+            Some((name, spec)) => {
+                let field = Field::from_name(name).ok_or_else(|| {
+                    eprintln!("ERROR: unknown field {name}. Available fields: body, title, headings, code");
+                })?;
+                self.specs.push((Some(field), spec.to_string()));
+            }
+            None => self.specs.push((None, value)),
+        }
+        Ok(true)
+    }
+
+    /// Updates the analyzers of the model. The fields that are not mentioned keep the analyzers
+    /// that are already stored in the index. Returns whether they have changed.
+    fn apply_to(&self, model: &mut Model) -> Result<bool, ()> {
+        let mut analyzers = model.analyzers().clone();
+        for (field, spec) in &self.specs {
+            let analyzer = Analyzer::parse(spec)?;
+            for f in Field::ALL.into_iter().filter(|f| field.is_none() || *field == Some(*f)) {
+                analyzers[f.index()] = analyzer.clone();
+            }
+        }
+        if let Some(language) = self.language {
+            analyzers.iter_mut().for_each(|analyzer| analyzer.set_language(language));
+        }
+        let changed = model.set_analyzers(analyzers);
+        if changed {
+            println!("INFO: analyzers have changed, indexing the folder from scratch");
+            for field in Field::ALL {
+                println!("INFO:     {name}: {analyzer}", name = field.name(), analyzer = model.analyzers()[field.index()]);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+fn parse_number_flag(flag: &str, value: Option<String>) -> Result<f32, ()> {
+    let value = value.ok_or_else(|| {
+        eprintln!("ERROR: no value is provided for flag {flag}");
+    })?;
+    value.parse().map_err(|err| {
+        eprintln!("ERROR: {value} is not a valid value for flag {flag}: {err}");
+    })
+}
+
+#[derive(Serialize)]
+struct SearchResults {
+    total: usize,
+    hits: Vec<SearchHit>,
+}
+
+fn print_search_results(results: &SearchResults) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    // Only bother with the colors when a human is looking at it
+    let (mark_start, mark_end) = if stdout.is_terminal() { ("\x1b[1;31m", "\x1b[0m") } else { ("", "") };
+    for hit in &results.hits {
+        writeln!(stdout, "{score:.4}\t{path}", score = hit.score, path = hit.path.display())?;
+        for snippet in &hit.snippets {
+            writeln!(stdout, "    {snippet}", snippet = snippet.render(mark_start, mark_end))?;
+        }
+    }
+    Ok(())
+}
+
+fn load_model_from_json(index_path: &Path, dir_path: &Path) -> Result<Model, ()> {
+    let index_file = File::open(index_path).map_err(|err| {
+        eprintln!("ERROR: could not open index file {index_path}: {err}",
+                  index_path = index_path.display());
+    })?;
+    let root = fs::canonicalize(dir_path).map_err(|err| {
+        eprintln!("ERROR: could not resolve directory {dir_path}: {err}",
+                  dir_path = dir_path.display());
+    })?;
+
+    Model::from_legacy_json(BufReader::new(index_file), dir_path, &root).map_err(|err| {
+        eprintln!("ERROR: could not parse index file {index_path}: {err}",
+                  index_path = index_path.display());
+    })
+}
+
+fn default_index_path(dir_path: &Path) -> PathBuf {
+    dir_path.join(".seroost.idx")
+}
+
+/// Loads the index of the folder. The JSON index of the previous versions gets converted into the
+/// binary format on the first load and its documents get reindexed. Its paths become absolute on
+/// the way (see `Model::from_legacy_json`). An index that can't be loaded is never fatal, the
+/// folder is just indexed from scratch.
+fn load_model(dir_path: &Path, index_path: &Path) -> Result<Model, ()> {
+    let file_exists = |file_path: &Path| file_path.try_exists().map_err(|err| {
+        eprintln!("ERROR: could not check the existence of file {file_path}: {err}",
+                  file_path = file_path.display());
+    });
+
+    if file_exists(index_path)? {
+        match index_file::load_model(index_path) {
+            Ok(model) => return Ok(model),
+            // The index is just a cache of the folder, it can always be rebuilt
+            Err(()) => eprintln!("WARNING: indexing the folder from scratch"),
+        }
+    }
+
+    // The old index is removed from the folder once migrated. The folder of an index that is
+    // written somewhere else may be only read, so its old index is left alone.
+    let json_path = dir_path.join(".seroost.json");
+    if index_path == default_index_path(dir_path) && file_exists(&json_path)? {
+        println!("INFO: migrating {json_path} to {index_path}...",
+                 json_path = json_path.display(),
+                 index_path = index_path.display());
+        match load_model_from_json(&json_path, dir_path) {
+            Ok(model) => {
+                // Keep the old index around until the new one is written, the migration is retried on the next run
+                if index_file::save_model(&model, index_path).is_ok() {
+                    fs::remove_file(&json_path).map_err(|err| {
+                        eprintln!("WARNING: could not remove the old index file {json_path}: {err}",
+                                  json_path = json_path.display());
+                    }).ok();
+                }
+                return Ok(model);
+            }
+            Err(()) => {
+                eprintln!("WARNING: the old index file can't be migrated. Indexing the folder from scratch.");
+            }
+        }
+    }
+
+    Ok(Default::default())
+}
+
+/// Writes the index file from a snapshot, so the searches are not blocked by the writing. Then the
+/// documents of the model are replaced by the ones of the written file, so they don't stay in
+/// memory. Returns whether all the changes of the model made it into the file.
+fn save_index(model: &Mutex<Model>, index_path: &Path) -> Result<bool, ()> {
+    let snapshot = model.lock().unwrap().snapshot();
+    index_file::save_model(&snapshot, index_path)?;
+    let Ok(base) = index_file::MappedIndex::open(index_path) else {
+        eprintln!("WARNING: keeping the documents of the index in memory");
+        return Ok(true);
+    };
+    let mut model = model.lock().unwrap();
+    // The changes made during the writing are not in the file, they have to wait for the next one
+    if model.changes() != snapshot.changes() {
+        return Ok(false);
+    }
+    *model = Model::from_index(base);
+    Ok(true)
 }
 
 fn entry() -> Result<(), ()> {
@@ -186,51 +708,246 @@ fn entry() -> Result<(), ()> {
 
     match subcommand.as_str() {
         "serve" => {
-            let dir_path = args.next().ok_or_else(|| {
+            let mut dir_path = None;
+            let mut address = None;
+            let mut scorer_options = ScorerOptions::default();
+            let mut analyzer_flags = AnalyzerFlags::default();
+            let mut filter_options = FilterOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    _ if scorer_options.parse_flag(&arg, &mut args)? => {}
+                    _ if analyzer_flags.parse_flag(&arg, &mut args)? => {}
+                    _ if filter_options.parse_flag(&arg, &mut args)? => {}
+                    _ if dir_path.is_none() => dir_path = Some(arg),
+                    _ if address.is_none() => address = Some(arg),
+                    _ => {
+                        usage(&program);
+                        eprintln!("ERROR: unexpected argument {arg} for {subcommand} subcommand");
+                        return Err(());
+                    }
+                }
+            }
+
+            let dir_path = dir_path.ok_or_else(|| {
+                usage(&program);
+                eprintln!("ERROR: no directory is provided for {subcommand} subcommand");
+            })?;
+
+            let index_path = default_index_path(Path::new(&dir_path));
+
+            let address = address.unwrap_or("127.0.0.1:6969".to_string());
+
+            let filter = filter_options.build(Path::new(&dir_path))?;
+            let mut model = load_model(Path::new(&dir_path), &index_path)?;
+            let scorer_changed = scorer_options.apply_to(&mut model)?;
+            let analyzers_changed = analyzer_flags.apply_to(&mut model)?;
+            let model = Arc::new(Mutex::new(model));
+
+            {
+                let model = Arc::clone(&model);
+                thread::spawn(move || {
+                    // Start watching before indexing, so the changes made in the meantime are not missed
+                    let watcher = watcher::Watcher::new(filter.clone()).map_err(|()| {
+                        eprintln!("WARNING: the changes of the folder will not be picked up until restart");
+                    }).ok();
+
+                    // The errors are reported where they happen. Whatever got indexed is still
+                    // served and the watcher picks up the rest of the changes.
+                    let mut stats = IndexingStats::default();
+                    match index_folder(&filter, Arc::clone(&model), &mut stats) {
+                        Ok(()) => println!("Finished indexing"),
+                        Err(()) => eprintln!("WARNING: the folder is only partially indexed"),
+                    }
+                    let mut unsaved = stats.model_changed() || scorer_changed || analyzers_changed;
+                    if unsaved {
+                        unsaved = !save_index(&model, &index_path).unwrap_or(false);
+                    }
+
+                    if let Some(watcher) = watcher {
+                        watcher.run(model, &index_path, unsaved).ok();
+                    }
+                });
+            }
+
+            server::start(&address, Arc::clone(&model))
+        }
+
+        "index" => {
+            let mut dir_path = None;
+            let mut output = None;
+            let mut scorer_options = ScorerOptions::default();
+            let mut analyzer_flags = AnalyzerFlags::default();
+            let mut filter_options = FilterOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    _ if scorer_options.parse_flag(&arg, &mut args)? => {}
+                    _ if analyzer_flags.parse_flag(&arg, &mut args)? => {}
+                    _ if filter_options.parse_flag(&arg, &mut args)? => {}
+                    "--output" => {
+                        output = Some(args.next().ok_or_else(|| {
+                            usage(&program);
+                            eprintln!("ERROR: no value is provided for flag {arg}");
+                        })?);
+                    }
+                    _ if dir_path.is_none() => dir_path = Some(arg),
+                    _ => {
+                        usage(&program);
+                        eprintln!("ERROR: unexpected argument {arg} for {subcommand} subcommand");
+                        return Err(());
+                    }
+                }
+            }
+
+            let dir_path = dir_path.ok_or_else(|| {
                 usage(&program);
                 eprintln!("ERROR: no directory is provided for {subcommand} subcommand");
             })?;
 
-            let mut index_path = Path::new(&dir_path).to_path_buf();
-            index_path.push(".seroost.json");
+            let index_path = match output {
+                Some(output) => PathBuf::from(output),
+                None => default_index_path(Path::new(&dir_path)),
+            };
+
+            let filter = filter_options.build(Path::new(&dir_path))?;
+            let mut model = load_model(Path::new(&dir_path), &index_path)?;
+            scorer_options.apply_to(&mut model)?;
+            analyzer_flags.apply_to(&mut model)?;
+            let model = Arc::new(Mutex::new(model));
+
+            let mut stats = IndexingStats::default();
+            index_folder(&filter, Arc::clone(&model), &mut stats)?;
+            index_file::save_model(&model.lock().unwrap(), &index_path)?;
+
+            println!("Indexed {indexed} files, skipped {skipped}, failed {failed}, removed {removed}",
+                     indexed = stats.indexed,
+                     skipped = stats.skipped,
+                     failed = stats.failed,
+                     removed = stats.removed);
+            Ok(())
+        }
 
-            let address = args.next().unwrap_or("127.0.0.1:6969".to_string());
+        "search" => {
+            let mut index_path = None;
+            let mut query_words = Vec::new();
+            let mut limit = DEFAULT_SEARCH_LIMIT;
+            let mut json = false;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--json" => json = true,
+                    "--limit" => {
+                        let value = args.next().ok_or_else(|| {
+                            usage(&program);
+                            eprintln!("ERROR: no value is provided for flag {arg}");
+                        })?;
+                        limit = value.parse().map_err(|err| {
+                            eprintln!("ERROR: {value} is not a valid value for flag {arg}: {err}");
+                        })?;
+                    }
+                    _ if index_path.is_none() => index_path = Some(PathBuf::from(arg)),
+                    // The query does not have to be quoted as a whole
+                    _ => query_words.push(arg),
+                }
+            }
 
-            let exists = index_path.try_exists().map_err(|err| {
-                eprintln!("ERROR: could not check the existence of file {index_path}: {err}",
-                          index_path = index_path.display());
+            let mut index_path = index_path.ok_or_else(|| {
+                usage(&program);
+                eprintln!("ERROR: no index or folder is provided for {subcommand} subcommand");
             })?;
+            if query_words.is_empty() {
+                usage(&program);
+                eprintln!("ERROR: no query is provided for {subcommand} subcommand");
+                return Err(());
+            }
 
-            let model: Arc<Mutex<Model>>;
-            if exists {
-                let index_file = File::open(&index_path).map_err(|err| {
-                    eprintln!("ERROR: could not open index file {index_path}: {err}",
+            if index_path.is_dir() {
+                index_path = default_index_path(&index_path);
+                if !index_path.exists() {
+                    eprintln!("ERROR: {index_path} does not exist. Run the index subcommand first.",
                               index_path = index_path.display());
-                })?;
+                    return Err(());
+                }
+            }
+            let model = index_file::load_model(&index_path)?;
 
-                model = Arc::new(Mutex::new(serde_json::from_reader(index_file).map_err(|err| {
-                    eprintln!("ERROR: could not parse index file {index_path}: {err}",
-                              index_path = index_path.display());
-                })?));
+            let query = query::parse(&query_words.join(" ").chars().collect::<Vec<_>>(), model.analyzers(), &model.languages()).map_err(|err| {
+                eprintln!("ERROR: invalid query: {err}");
+            })?;
+
+            let matches = model.search_query(&query);
+            let top = &matches[..matches.len().min(limit)];
+            let snippets = model.snippets(&top.iter().map(|(id, _)| *id).collect::<Vec<_>>(), &query);
+            let results = SearchResults {
+                total: matches.len(),
+                hits: top
+                    .iter()
+                    .zip(snippets)
+                    .filter_map(|((id, score), snippets)| Some(SearchHit::new(&model.doc(*id)?, *score).finish(snippets)))
+                    .collect(),
+            };
+
+            if json {
+                serde_json::to_writer(io::stdout().lock(), &results).map_err(|err| {
+                    eprintln!("ERROR: could not write search results as JSON: {err}");
+                })?;
+                println!();
             } else {
-                model = Arc::new(Mutex::new(Default::default()));
+                print_search_results(&results).map_err(|err| {
+                    eprintln!("ERROR: could not print search results: {err}");
+                })?;
             }
+            Ok(())
+        }
 
-            {
-                let model = Arc::clone(&model);
-                thread::spawn(move || {
-                    let mut processed = 0;
-                    // TODO: what should we do in case indexing thread crashes
-                    add_folder_to_model(Path::new(&dir_path), Arc::clone(&model), &mut processed).unwrap();
-                    if processed > 0 {
-                        let model = model.lock().unwrap();
-                        save_model_as_json(&model, &index_path).unwrap();
+        "repl" => {
+            let mut dir_path = None;
+            let mut reindex = false;
+            let mut filter_options = FilterOptions::default();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--reindex" => reindex = true,
+                    _ if filter_options.parse_flag(&arg, &mut args)? => {}
+                    _ if dir_path.is_none() => dir_path = Some(arg),
+                    _ => {
+                        usage(&program);
+                        eprintln!("ERROR: unexpected argument {arg} for {subcommand} subcommand");
+                        return Err(());
                     }
-                    println!("Finished indexing");
-                });
+                }
             }
 
-            server::start(&address, Arc::clone(&model))
+            let dir_path = dir_path.ok_or_else(|| {
+                usage(&program);
+                eprintln!("ERROR: no directory is provided for {subcommand} subcommand");
+            })?;
+            if !reindex && !filter_options.is_empty() {
+                eprintln!("ERROR: --include and --exclude are only applicable with --reindex");
+                return Err(());
+            }
+
+            let index_path = default_index_path(Path::new(&dir_path));
+            let mut model = if reindex {
+                let filter = filter_options.build(Path::new(&dir_path))?;
+                let model = Arc::new(Mutex::new(load_model(Path::new(&dir_path), &index_path)?));
+                let mut stats = IndexingStats::default();
+                index_folder(&filter, Arc::clone(&model), &mut stats)?;
+                let model = Arc::into_inner(model).expect("the indexing threads are done").into_inner().unwrap();
+                if stats.model_changed() {
+                    index_file::save_model(&model, &index_path)?;
+                }
+                model
+            } else {
+                // Only exploring, e.g. the index that was built somewhere else
+                if !index_path.exists() {
+                    eprintln!("ERROR: {index_path} does not exist. Run the index subcommand or pass --reindex.",
+                              index_path = index_path.display());
+                    return Err(());
+                }
+                index_file::load_model(&index_path)?
+            };
+
+            // The documents are indexed with the canonical paths (see `FileFilter`)
+            let dir_path = fs::canonicalize(&dir_path).unwrap_or_else(|_| PathBuf::from(dir_path));
+            repl::start(&dir_path, &mut model)
         }
 
         _ => {
@@ -0,0 +1,127 @@
+// Extracts the text of a Markdown file with pulldown-cmark. The first H1 is the title, the other
+// headings and the code are collected for their own fields, the HTML embedded into the Markdown
+// goes through the HTML parser.
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use super::html;
+use super::model::ParsedDoc;
+
+/// The heading being parsed. The text of the title does not go to the body.
+struct Heading {
+    text: String,
+    is_title: bool,
+}
+
+fn push_text(doc: &mut ParsedDoc, heading: &mut Option<Heading>, text: &str) {
+    match heading {
+        Some(Heading { text: title, is_title: true }) => title.push_str(text),
+        Some(Heading { text: heading, is_title: false }) => {
+            heading.push_str(text);
+            doc.body.push_str(text);
+        }
+        None => doc.body.push_str(text),
+    }
+}
+
+/// Only the text gets through. The markup and link destinations are dropped. The code stays in
+/// the body as well, so its matches get the snippets.
+pub fn parse(source: &str) -> ParsedDoc {
+    let mut doc = ParsedDoc::default();
+    let mut has_title = false;
+    let mut heading = None;
+    let mut code_block = None;
+    let mut html_block = String::new();
+    for event in Parser::new_ext(source, Options::ENABLE_TABLES) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                let is_title = level == HeadingLevel::H1 && !has_title;
+                has_title |= is_title;
+                heading = Some(Heading { text: String::new(), is_title });
+            }
+            Event::End(TagEnd::Heading(_)) => match heading.take() {
+                Some(Heading { text, is_title: true }) => doc.title = text.trim().to_string(),
+                Some(Heading { text, is_title: false }) => {
+                    doc.headings.push(text);
+                    doc.body.push('\n');
+                }
+                None => {}
+            },
+            Event::Start(Tag::CodeBlock(_)) => code_block = Some(String::new()),
+            Event::End(TagEnd::CodeBlock) => {
+                doc.code.extend(code_block.take());
+                doc.body.push('\n');
+            }
+            Event::Text(text) => {
+                push_text(&mut doc, &mut heading, &text);
+                if let Some(code_block) = &mut code_block {
+                    code_block.push_str(&text);
+                }
+            }
+            Event::Code(text) => {
+                push_text(&mut doc, &mut heading, &text);
+                doc.code.push(text.into_string());
+            }
+            // A block comes line by line, the tags may span several lines
+            Event::Html(html) => html_block.push_str(&html),
+            Event::End(TagEnd::HtmlBlock) => {
+                let parsed = html::parse(&html_block);
+                doc.body.push_str(&parsed.body);
+                doc.body.push('\n');
+                doc.headings.extend(parsed.headings);
+                html_block.clear();
+            }
+            Event::InlineHtml(html) => push_text(&mut doc, &mut heading, &html::parse(&html).body),
+            Event::SoftBreak | Event::HardBreak => push_text(&mut doc, &mut heading, " "),
+            // Separate the blocks, so the words on their borders don't stick together
+            Event::End(TagEnd::Paragraph | TagEnd::Item | TagEnd::TableCell | TagEnd::BlockQuote(_)) => {
+                doc.body.push('\n');
+            }
+            _ => {}
+        }
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text with the whitespace collapsed.
+    fn words(text: &str) -> String {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// (source, title, body, headings, code)
+    type Case<'a> = (&'a str, &'a str, &'a str, &'a [&'a str], &'a [&'a str]);
+
+    #[test]
+    fn fields() {
+        let cases: [Case; 11] = [
+            ("# Title\n\nSome *emphasized* text", "Title", "Some emphasized text", &[], &[]),
+            ("Setext\n======\ntext\n\n## Second `code` heading", "Setext", "text Second code heading", &["Second code heading"], &["code"]),
+            ("## Intro\n\n# The *real* title\n\n# Another one", "The real title", "Intro Another one", &["Intro", "Another one"], &[]),
+            ("See [the docs](https://example.com/docs) and <https://example.com>", "", "See the docs and https://example.com", &[], &[]),
+            ("![a diagram](diagram.png \"Diagram\")", "", "a diagram", &[], &[]),
+            ("Call `parse()` first", "", "Call parse() first", &[], &["parse()"]),
+            ("```rust\nfn main() {}\n```\nafter", "", "fn main() {} after", &[], &["fn main() {}"]),
+            ("    indented code\n\nparagraph", "", "indented code paragraph", &[], &["indented code"]),
+            ("first<br>second <b>bold</b>", "", "first second bold", &[], &[]),
+            ("<div>\n<h2>Embedded</h2>\n<p>block &amp; html</p>\n</div>\n\ntext", "", "Embedded block & html text", &["Embedded"], &[]),
+            ("| a | b |\n|---|---|\n| c | d |\n\n- item\n- other", "", "a b c d item other", &[], &[]),
+        ];
+        for (source, title, body, headings, code) in cases {
+            let doc = parse(source);
+            assert_eq!(doc.title, title, "{source:?}");
+            assert_eq!(words(&doc.body), body, "{source:?}");
+            assert_eq!(doc.headings.iter().map(|heading| words(heading)).collect::<Vec<_>>(), headings, "{source:?}");
+            assert_eq!(doc.code.iter().map(|code| words(code)).collect::<Vec<_>>(), code, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn blocks_are_separated() {
+        let doc = parse("## Heading\nparagraph\n\n- item\n- other\n\n> quote\n\n```\ncode\n```\nend");
+        assert_eq!(doc.body.lines().filter(|line| !line.is_empty()).collect::<Vec<_>>(), ["Heading", "paragraph", "item", "other", "quote", "code", "end"]);
+    }
+}
@@ -1,97 +1,1183 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Read;
+use std::iter;
 use std::path::{PathBuf, Path};
-use serde::{Deserialize, Serialize};
-use super::lexer::Lexer;
+use std::sync::Arc;
+use serde::Deserialize;
+use super::analyzer::{FieldAnalyzers, Stemming, Terms};
+use super::language::Language;
+use super::query::{FieldClause, Query};
+use super::index_file::{EncodedPositions, MappedIndex, TermEntry};
+use super::snippet::{self, SnippetDraft, Spans};
 use std::time::SystemTime;
 
-type DocFreq = HashMap<String, usize>;
+pub type DocId = u32;
 type TermFreq = HashMap<String, usize>;
-#[derive(Deserialize, Serialize)]
+/// Lengths of the fields in terms indexed by `Field::index`.
+pub type FieldLengths = [usize; Field::ALL.len()];
+/// Amounts of the documents of the languages indexed by `Language::index`.
+pub type LanguageCounts = [usize; Language::ALL.len()];
+#[derive(Clone)]
 pub struct Doc {
+    path: PathBuf,
+    /// The terms of the document. Needed to find the postings of the document on removal.
     tf: TermFreq,
-    count: usize,
-    // TODO: make sure that the serde serialization of SystemTime also work on other platforms
+    lengths: FieldLengths,
+    /// `None` if the language could not be detected.
+    language: Option<Language>,
     last_modified: SystemTime,
+    /// The text the document was indexed from. Used to show the snippets of the matches.
+    text: String,
+    /// The ranges of the tokens of the body in `text` (see `Spans`).
+    spans: Vec<u8>,
+}
+type Docs = HashMap<DocId, Doc>;
+/// Documents that contain the term with the positions of the term in them counted in tokens.
+pub type Postings = BTreeMap<DocId, Vec<usize>>;
+
+/// The postings of a term in the index file followed by the ones in memory. The postings of the
+/// index file are decoded while iterating them.
+pub struct TermPostings<'a> {
+    mapped: Option<TermEntry<'a>>,
+    memory: Option<&'a Postings>,
+    deleted: &'a HashSet<DocId>,
+}
+
+impl<'a> TermPostings<'a> {
+    /// The documents in the ascending order of their ids. The deleted ones are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = (DocId, Positions<'a>)> + 'a {
+        let deleted = self.deleted;
+        let mapped = self.mapped.into_iter()
+            .flat_map(|entry| entry.postings())
+            .filter(move |(id, _)| !deleted.contains(id))
+            .map(|(id, positions)| (id, Positions::Mapped(positions)));
+        let memory = self.memory.into_iter().flatten().map(|(id, positions)| (*id, Positions::Memory(positions)));
+        mapped.chain(memory)
+    }
+
+    pub fn doc_freq(&self) -> usize {
+        let memory = self.memory.map_or(0, |memory| memory.len());
+        match self.mapped {
+            Some(entry) if self.deleted.is_empty() => entry.doc_freq + memory,
+            Some(entry) => entry.postings().filter(|(id, _)| !self.deleted.contains(id)).count() + memory,
+            None => memory,
+        }
+    }
+}
+
+/// The positions of a term in a document. The ones of the index file are decoded on demand.
+pub enum Positions<'a> {
+    Mapped(EncodedPositions<'a>),
+    Memory(&'a [usize]),
+}
+
+impl<'a> Positions<'a> {
+    pub fn decode(&self) -> Cow<'a, [usize]> {
+        match self {
+            Positions::Mapped(positions) => Cow::Owned(positions.decode()),
+            Positions::Memory(positions) => Cow::Borrowed(positions),
+        }
+    }
+}
+
+/// A document of the model regardless of whether it lives in memory or in the mapped index file.
+pub struct DocInfo<'a> {
+    pub path: &'a Path,
+    pub lengths: FieldLengths,
+    pub language: Option<Language>,
+    pub last_modified: SystemTime,
+    pub text: &'a str,
+    pub spans: Spans<'a>,
+}
+
+/// Marks the keys of the terms of the fields other than the body (see `Field::term_key`).
+const FIELD_KEY_PREFIX: char = '\u{0}';
+
+/// The parts of a document that are indexed separately, so the matches in them can be ranked
+/// differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The whole text of the document.
+    Body,
+    Title,
+    Headings,
+    /// The code of the document, e.g. the code blocks of Markdown.
+    Code,
+}
+
+impl Field {
+    pub const ALL: [Field; 4] = [Field::Body, Field::Title, Field::Headings, Field::Code];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL.into_iter().find(|field| field.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Body => "body",
+            Field::Title => "title",
+            Field::Headings => "headings",
+            Field::Code => "code",
+        }
+    }
+
+    /// How much a match in the field weighs compared to a match in the body.
+    pub fn boost(self) -> f32 {
+        match self {
+            Field::Body => 1.0,
+            Field::Title => 3.0,
+            Field::Headings => 2.0,
+            // The code is in the body too, the extra weight favors the documents that have the
+            // terms in their code, not just mention them
+            Field::Code => 1.0,
+        }
+    }
+
+    /// The key of the term in the postings. The terms of the fields other than the body are
+    /// prefixed with `\0` and the index of the field. The lexer never produces the control
+    /// characters, so no analyzer can make a term of the body that clashes with them.
+    pub fn term_key(self, term: &str) -> Cow<'_, str> {
+        match self {
+            Field::Body => Cow::Borrowed(term),
+            _ => Cow::Owned(format!("{FIELD_KEY_PREFIX}{index}{term}", index = self.index())),
+        }
+    }
+
+    /// Splits the key of the postings into the field and the term (see `term_key`).
+    pub fn parse_term_key(key: &str) -> (Field, &str) {
+        let field = key
+            .strip_prefix(FIELD_KEY_PREFIX)
+            .and_then(|rest| Some((rest.get(..1)?.parse::<usize>().ok()?, &rest[1..])))
+            .and_then(|(index, term)| Some((*Field::ALL.get(index)?, term)));
+        field.unwrap_or((Field::Body, key))
+    }
+}
+
+/// A document of the JSON index of the versions before the binary index file.
+#[derive(Deserialize)]
+struct LegacyDoc {
+    tf: TermFreq,
+    count: usize,
+}
+
+/// The JSON index of the versions before the binary index file. The documents are keyed by their
+/// paths. The document frequencies (`df`) are not read, the postings give them.
+#[derive(Deserialize)]
+struct LegacyModel {
+    docs: HashMap<PathBuf, LegacyDoc>,
+}
+
+/// A document as it comes out of a parser.
+#[derive(Default)]
+pub struct ParsedDoc {
+    /// The whole text of the document including the headings and the code but not the title.
+    pub body: String,
+    pub title: String,
+    pub headings: Vec<String>,
+    /// The code blocks and the inline code.
+    pub code: Vec<String>,
+}
+
+impl ParsedDoc {
+    pub fn from_body(body: String) -> Self {
+        ParsedDoc { body, ..Default::default() }
+    }
 }
-type Docs = HashMap<PathBuf, Doc>;
 
-#[derive(Default, Deserialize, Serialize)]
+/// How the rank of a document was computed. Mirrors the structure of the query.
+pub struct Explanation {
+    pub description: String,
+    /// `None` if the document does not match this part of the query.
+    pub value: Option<f32>,
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn new(description: impl Into<String>, value: Option<f32>) -> Self {
+        Self { description: description.into(), value, details: vec![] }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scorer {
+    /// Okapi BM25. `k1` controls the term frequency saturation, `b` controls
+    /// how much the document length is normalized against the average one.
+    Bm25 { k1: f32, b: f32 },
+    /// The classic TF-IDF. Kept around for comparing the quality of the results.
+    TfIdf,
+}
+
+impl Scorer {
+    pub const DEFAULT_K1: f32 = 1.2;
+    pub const DEFAULT_B: f32 = 0.75;
+}
+
+impl Default for Scorer {
+    fn default() -> Self {
+        Scorer::Bm25 { k1: Self::DEFAULT_K1, b: Self::DEFAULT_B }
+    }
+}
+
+/// The documents added and removed since the model was loaded from the index file.
+#[derive(Default, Clone)]
+struct Delta {
+    docs: Docs,
+    /// The inverted index of `docs`.
+    postings: HashMap<String, Postings>,
+    /// Documents of the index file that were removed or reindexed since it was loaded.
+    deleted: HashSet<DocId>,
+}
+
+#[derive(Default)]
 pub struct Model {
-    pub docs: Docs,
-    pub df: DocFreq,
+    /// Shared with the snapshots of the model, so it is copied only if the model changes while
+    /// one of them is still around (see `snapshot`).
+    delta: Arc<Delta>,
+    /// Sums of the lengths of the fields of all the documents in the model. Used to compute the
+    /// average field lengths for BM25.
+    total_lengths: FieldLengths,
+    /// The languages of the documents in the model. The queries are stemmed in all of them.
+    language_counts: LanguageCounts,
+    pub scorer: Scorer,
+    /// Stored in the index file, so the queries are analyzed the same way as the documents.
+    analyzers: FieldAnalyzers,
+    next_doc_id: DocId,
+    ids: HashMap<PathBuf, DocId>,
+    /// The index file the model was loaded from. It is searched in place, so loading it does not
+    /// depend on its size. The ids of its documents go before the ids of the documents of `delta`.
+    base: Option<Arc<MappedIndex>>,
+    /// The paths of `base` are added to `ids` only when the model gets modified, because
+    /// searching does not need them.
+    base_ids_loaded: bool,
+    /// How many times the documents have changed. Tells whether the model has changed since a
+    /// snapshot was taken (see `snapshot`).
+    changes: u64,
 }
 
 impl Model {
-    fn remove_document(&mut self, file_path: &Path) {
-        if let Some(doc) = self.docs.remove(file_path) {
-            for t in doc.tf.keys() {
-                if let Some(f) = self.df.get_mut(t) {
-                    *f -= 1;
+    pub fn from_index(base: MappedIndex) -> Self {
+        Model {
+            scorer: base.scorer,
+            analyzers: base.analyzers.clone(),
+            total_lengths: base.total_lengths,
+            language_counts: base.language_counts,
+            next_doc_id: base.docs_count as DocId,
+            base: Some(Arc::new(base)),
+            ..Default::default()
+        }
+    }
+
+    /// Converts the JSON index of the previous versions. It has neither the positions of the terms
+    /// nor the texts, so the documents are marked for reindexing. Until then they are found only by
+    /// their terms: the made up positions are never adjacent, so no phrase matches them.
+    ///
+    /// The paths of the JSON index start with the folder as it was spelled on the command line,
+    /// while the documents are now indexed by their canonical paths (see `FileFilter`). So the
+    /// paths inside of `dir_path` are moved to `root`, its canonical path.
+    pub fn from_legacy_json(reader: impl Read, dir_path: &Path, root: &Path) -> serde_json::Result<Model> {
+        let legacy: LegacyModel = serde_json::from_reader(reader)?;
+        let mut model = Model::default();
+        for (file_path, doc) in legacy.docs {
+            let file_path = match file_path.strip_prefix(dir_path) {
+                Ok(relative) => root.join(relative),
+                Err(_) => file_path,
+            };
+            let positions = doc.tf.into_iter().map(|(t, f)| (t, (0..f).map(|i| 2*i).collect())).collect();
+            let mut lengths = FieldLengths::default();
+            lengths[Field::Body.index()] = doc.count;
+            let doc = AnalyzedDoc { positions, lengths, language: None, text: String::new(), spans: Vec::new() };
+            // Older than any modification time of the file
+            model.insert_document(file_path, SystemTime::UNIX_EPOCH, doc);
+        }
+        Ok(model)
+    }
+
+    pub fn analyzers(&self) -> &FieldAnalyzers {
+        &self.analyzers
+    }
+
+    /// The terms of the documents depend on the analyzers, so changing them drops all the
+    /// documents. Returns whether they have changed.
+    pub fn set_analyzers(&mut self, analyzers: FieldAnalyzers) -> bool {
+        if analyzers == self.analyzers {
+            return false;
+        }
+        *self = Model { scorer: self.scorer, analyzers, changes: self.changes + 1, ..Default::default() };
+        true
+    }
+
+    /// A copy of what writing the index file needs, so it can be written without the lock of the
+    /// model. It shares the documents with the model instead of copying them.
+    pub fn snapshot(&self) -> Model {
+        Model {
+            delta: Arc::clone(&self.delta),
+            total_lengths: self.total_lengths,
+            language_counts: self.language_counts,
+            scorer: self.scorer,
+            analyzers: self.analyzers.clone(),
+            next_doc_id: self.next_doc_id,
+            base: self.base.clone(),
+            changes: self.changes,
+            ..Default::default()
+        }
+    }
+
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    fn load_base_ids(&mut self) {
+        if self.base_ids_loaded {
+            return;
+        }
+        if let Some(base) = &self.base {
+            for id in 0..base.docs_count as DocId {
+                if let Some(entry) = base.doc(id) {
+                    self.ids.insert(PathBuf::from(entry.path), id);
                 }
             }
         }
+        self.base_ids_loaded = true;
+    }
+
+    pub fn remove_document(&mut self, file_path: &Path) {
+        self.load_base_ids();
+        if let Some(id) = self.ids.remove(file_path) {
+            self.changes += 1;
+            let delta = Arc::make_mut(&mut self.delta);
+            let (lengths, language) = if let Some(doc) = delta.docs.remove(&id) {
+                for t in doc.tf.keys() {
+                    if let Some(postings) = delta.postings.get_mut(t) {
+                        postings.remove(&id);
+                        if postings.is_empty() {
+                            delta.postings.remove(t);
+                        }
+                    }
+                }
+                (doc.lengths, doc.language)
+            } else if let Some(entry) = self.base.as_ref().and_then(|base| base.doc(id)) {
+                delta.deleted.insert(id);
+                (entry.lengths, entry.language)
+            } else {
+                return;
+            };
+            self.subtract_stats(&lengths, language);
+        }
+    }
+
+    fn subtract_stats(&mut self, lengths: &FieldLengths, language: Option<Language>) {
+        for (total, length) in self.total_lengths.iter_mut().zip(lengths) {
+            *total -= length;
+        }
+        if let Some(language) = language {
+            self.language_counts[language.index()] -= 1;
+        }
+    }
+
+    /// Removes the document of the file or all the documents inside of the directory. Returns the
+    /// amount of the removed documents.
+    pub fn remove_path(&mut self, path: &Path) -> usize {
+        self.load_base_ids();
+        let paths = self.ids.keys().filter(|file_path| file_path.starts_with(path)).cloned().collect::<Vec<_>>();
+        for file_path in &paths {
+            self.remove_document(file_path);
+        }
+        paths.len()
     }
 
     pub fn requires_reindexing(&mut self, file_path: &Path, last_modified: SystemTime) -> bool {
-        if let Some(doc) = self.docs.get(file_path) {
+        self.load_base_ids();
+        if let Some(doc) = self.ids.get(file_path).and_then(|id| self.doc(*id)) {
             return doc.last_modified < last_modified;
         }
         return true;
     }
 
-    pub fn search_query(&self, query: &[char]) -> Vec<(PathBuf, f32)> {
-        let mut result = Vec::new();
-        let tokens = Lexer::new(&query).collect::<Vec<_>>();
-        for (path, doc) in &self.docs {
-            let mut rank = 0f32;
-            for token in &tokens {
-                rank += compute_tf(token, doc) * compute_idf(&token, self.docs.len(), &self.df);
+    pub fn find_doc(&mut self, file_path: &Path) -> Option<DocId> {
+        self.load_base_ids();
+        self.ids.get(file_path).cloned()
+    }
+
+    pub fn doc(&self, id: DocId) -> Option<DocInfo<'_>> {
+        if let Some(doc) = self.delta.docs.get(&id) {
+            return Some(DocInfo {
+                path: &doc.path,
+                lengths: doc.lengths,
+                language: doc.language,
+                last_modified: doc.last_modified,
+                text: &doc.text,
+                spans: Spans(&doc.spans),
+            });
+        }
+        if self.delta.deleted.contains(&id) {
+            return None;
+        }
+        let entry = self.base.as_ref()?.doc(id)?;
+        Some(DocInfo {
+            path: Path::new(entry.path),
+            lengths: entry.lengths,
+            language: entry.language,
+            last_modified: entry.last_modified,
+            text: entry.text,
+            spans: Spans(entry.spans),
+        })
+    }
+
+    /// Ids of all the documents in the ascending order.
+    pub fn doc_ids(&self) -> Vec<DocId> {
+        let mut ids = Vec::with_capacity(self.docs_count());
+        if let Some(base) = &self.base {
+            ids.extend((0..base.docs_count as DocId).filter(|id| !self.delta.deleted.contains(id)));
+        }
+        let start = ids.len();
+        ids.extend(self.delta.docs.keys().cloned());
+        ids[start..].sort();
+        ids
+    }
+
+    pub fn docs_count(&self) -> usize {
+        let base_count = self.base.as_ref().map(|base| base.docs_count).unwrap_or(0);
+        base_count - self.delta.deleted.len() + self.delta.docs.len()
+    }
+
+    /// The number of the unique terms of the body that are still in some document. The postings
+    /// of the index file are walked only if some of its documents were removed.
+    pub fn terms_count(&self) -> usize {
+        let is_body = |key: &&str| Field::parse_term_key(key).0 == Field::Body;
+        let Some(base) = self.base.as_ref().filter(|_| self.delta.deleted.is_empty()) else {
+            return self.terms_from("").filter(is_body).filter(|key| self.term_postings(key).doc_freq() > 0).count();
+        };
+        // The keys of the other fields start with `FIELD_KEY_PREFIX`, so they go before the terms of the body
+        let base_count = base.terms_count - base.term_index("\u{1}").unwrap_or(base.terms_count);
+        let added = self.delta.postings.keys().map(String::as_str).filter(is_body).filter(|term| base.find_term(term).is_none()).count();
+        base_count + added
+    }
+
+    /// The terms in the ascending order starting from the first one that is not less than `start`.
+    /// The terms of the index file are read from its sorted table as the iteration goes.
+    pub fn terms_from<'a>(&'a self, start: &str) -> impl Iterator<Item = &'a str> {
+        let mut memory = self.delta.postings.keys().map(String::as_str).filter(|term| *term >= start).collect::<Vec<_>>();
+        memory.sort_unstable();
+        let mut memory = memory.into_iter().peekable();
+        let mut mapped = self.base.as_deref().map(|base| base.terms_from(start)).into_iter().flatten().peekable();
+        iter::from_fn(move || {
+            match (memory.peek(), mapped.peek()) {
+                (Some(a), Some(b)) if a < b => memory.next(),
+                (Some(a), Some(b)) if a > b => mapped.next(),
+                (Some(_), Some(_)) => {
+                    mapped.next();
+                    memory.next()
+                }
+                (Some(_), None) => memory.next(),
+                (None, _) => mapped.next(),
             }
-            // TODO: investigate the sources of NaN
-            if !rank.is_nan() {
-                result.push((path.clone(), rank));
+        })
+    }
+
+    pub fn term_postings<'a>(&'a self, term: &str) -> TermPostings<'a> {
+        TermPostings {
+            mapped: self.base.as_ref().and_then(|base| base.find_term(term)),
+            memory: self.delta.postings.get(term),
+            deleted: &self.delta.deleted,
+        }
+    }
+
+    /// The languages detected in the documents of the model.
+    pub fn languages(&self) -> Vec<Language> {
+        Language::ALL.into_iter().filter(|language| self.language_counts[language.index()] > 0).collect()
+    }
+
+    pub fn language_count(&self, language: Language) -> usize {
+        self.language_counts[language.index()]
+    }
+
+    pub fn average_field_len(&self, field: Field) -> f32 {
+        let n = self.docs_count();
+        if n == 0 {
+            return 0f32;
+        }
+        self.total_lengths[field.index()] as f32 / n as f32
+    }
+
+    /// Returns the ids of the documents that match the query along with their ranks. The best
+    /// matches go first.
+    pub fn search_query(&self, query: &Query) -> Vec<(DocId, f32)> {
+        let mut search = Search::new(self, query);
+        let mut result = Vec::new();
+        let ids = match search.candidates(query) {
+            Some(ids) => ids.into_iter().collect(),
+            None => self.doc_ids(),
+        };
+        search.decode_positions(&ids);
+        for id in ids {
+            let Some(doc) = self.doc(id) else {
+                continue;
+            };
+            if let Some(rank) = search.eval_query(query, id, &doc) {
+                // TODO: investigate the sources of NaN
+                if !rank.is_nan() {
+                    result.push((id, rank));
+                }
             }
         }
-        result.sort_by(|(_, rank1), (_, rank2)| rank1.partial_cmp(rank2).expect(&format!("{rank1} and {rank2} are not comparable")));
+        result.sort_by(|(_, rank1), (_, rank2)| rank1.partial_cmp(rank2).unwrap_or_else(|| panic!("{rank1} and {rank2} are not comparable")));
         result.reverse();
         result
     }
 
-    pub fn add_document(&mut self, file_path: PathBuf, last_modified: SystemTime, content: &[char]) {
+    /// Explains the rank of the document for the query. Returns `None` if there is no such document.
+    pub fn explain(&self, id: DocId, query: &Query) -> Option<Explanation> {
+        let doc = self.doc(id)?;
+        let mut search = Search::new(self, query);
+        search.decode_positions(&[id]);
+        Some(search.explain_query(query, id, &doc))
+    }
+
+    /// Drafts the snippets of the documents. The matches come from the postings of the terms of
+    /// the query, so the texts of the documents are not analyzed again.
+    pub fn snippets(&self, ids: &[DocId], query: &Query) -> Vec<SnippetDraft> {
+        // The query is analyzed for the body, so the matches in the other fields are highlighted in the body too
+        let analyzer = &self.analyzers[Field::Body.index()];
+        let languages = self.languages();
+        let mut terms = query
+            .matching_texts()
+            .into_iter()
+            .flat_map(|text| analyzer.analyze_with(text, Stemming::Also(&languages)).flat_map(|(terms, _)| terms))
+            .collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+        // Only the positions in the documents of the snippets are decoded
+        let mut matches_of = HashMap::<DocId, Vec<(usize, &str)>>::new();
+        for t in &terms {
+            for (id, positions) in self.term_postings(t).iter().filter(|(id, _)| ids.contains(id)) {
+                matches_of.entry(id).or_default().extend(positions.decode().iter().map(|position| (*position, t.as_str())));
+            }
+        }
+        ids.iter().map(|id| {
+            let Some(doc) = self.doc(*id) else {
+                return SnippetDraft::default();
+            };
+            let mut matches = matches_of.remove(id).unwrap_or_default();
+            matches.sort();
+            matches.dedup_by_key(|(position, _)| *position);
+            snippet::draft(doc.text, doc.spans, &matches)
+        }).collect()
+    }
+
+    pub fn insert_document(&mut self, file_path: PathBuf, last_modified: SystemTime, doc: AnalyzedDoc) {
         self.remove_document(&file_path);
 
+        let id = self.next_doc_id;
+        self.next_doc_id += 1;
+        self.changes += 1;
+
+        let delta = Arc::make_mut(&mut self.delta);
         let mut tf = TermFreq::new();
+        for (t, positions) in doc.positions {
+            tf.insert(t.clone(), positions.len());
+            delta.postings.entry(t).or_default().insert(id, positions);
+        }
 
-        let mut count = 0;
-        for t in Lexer::new(content) {
-            if let Some(f) = tf.get_mut(&t) {
-                *f += 1;
-            } else {
-                tf.insert(t, 1);
+        for (total, length) in self.total_lengths.iter_mut().zip(doc.lengths) {
+            *total += length;
+        }
+        if let Some(language) = doc.language {
+            self.language_counts[language.index()] += 1;
+        }
+        self.ids.insert(file_path.clone(), id);
+        delta.docs.insert(id, Doc {path: file_path, lengths: doc.lengths, language: doc.language, tf, last_modified, text: doc.text, spans: doc.spans});
+    }
+}
+
+/// A document analyzed independently of the model, so it can be done outside of its lock.
+pub struct AnalyzedDoc {
+    /// Positions of the terms in the document counted in tokens. The alternatives of a token
+    /// (synonyms, n-grams) share its position.
+    positions: HashMap<String, Vec<usize>>,
+    lengths: FieldLengths,
+    language: Option<Language>,
+    text: String,
+    spans: Vec<u8>,
+}
+
+impl AnalyzedDoc {
+    /// The words are stemmed in the language of the document unless it is unknown.
+    pub fn new(doc: ParsedDoc, language: Option<Language>, analyzers: &FieldAnalyzers) -> Self {
+        let mut positions = HashMap::<String, Vec<usize>>::new();
+        let mut lengths = FieldLengths::default();
+        let mut spans = Vec::new();
+        let mut add = |field: Field, text: &str, start: usize| -> usize {
+            let mut position = start;
+            for (terms, span) in analyzers[field.index()].analyze_with(text, Stemming::of_doc(language)) {
+                // The dropped tokens keep their spans too, so the spans stay in line with the positions
+                if field == Field::Body {
+                    Spans::push(&mut spans, span);
+                }
+                if !terms.is_empty() {
+                    lengths[field.index()] += 1;
+                }
+                for t in terms {
+                    positions.entry(field.term_key(&t).into_owned()).or_default().push(position);
+                }
+                position += 1;
+            }
+            position
+        };
+
+        add(Field::Body, &doc.body, 0);
+        add(Field::Title, &doc.title, 0);
+        // So the phrases don't match across the headings and the pieces of code
+        for (field, texts) in [(Field::Headings, &doc.headings), (Field::Code, &doc.code)] {
+            let mut position = 0;
+            for text in texts {
+                position = add(field, text, position) + 1;
             }
-            count += 1;
         }
 
-        for t in tf.keys() {
-            if let Some(f) = self.df.get_mut(t) {
-                *f += 1;
-            } else {
-                self.df.insert(t.to_string(), 1);
+        AnalyzedDoc { positions, lengths, language, text: doc.body, spans }
+    }
+}
+
+/// The state of a single query evaluation. Holds the postings of all the terms of the query.
+struct Search<'a> {
+    model: &'a Model,
+    /// The keys are the terms of the fields (see `Field::term_key`).
+    terms: HashMap<String, SearchTerm<'a>>,
+    n: usize,
+    /// Average lengths of the fields indexed by `Field::index`.
+    avgdl: [f32; Field::ALL.len()],
+}
+
+struct SearchTerm<'a> {
+    postings: TermPostings<'a>,
+    doc_freq: usize,
+    /// The positions of the term in the evaluated documents (see `Search::decode_positions`).
+    positions: HashMap<DocId, Cow<'a, [usize]>>,
+}
+
+impl<'a> Search<'a> {
+    fn new(model: &'a Model, query: &'a Query) -> Self {
+        let mut search = Search {
+            model,
+            terms: HashMap::new(),
+            n: model.docs_count(),
+            avgdl: Field::ALL.map(|field| model.average_field_len(field)),
+        };
+        for clause in query.clauses() {
+            for t in clause.positions.iter().flatten() {
+                let key = clause.field.term_key(t);
+                if !search.terms.contains_key(key.as_ref()) {
+                    let postings = model.term_postings(&key);
+                    let doc_freq = postings.doc_freq();
+                    search.terms.insert(key.into_owned(), SearchTerm { postings, doc_freq, positions: HashMap::new() });
+                }
+            }
+        }
+        search
+    }
+
+    /// Decodes the positions of the terms in the documents about to be evaluated. The ids must be
+    /// in the ascending order.
+    fn decode_positions(&mut self, ids: &[DocId]) {
+        for term in self.terms.values_mut() {
+            term.positions = term.postings.iter()
+                .filter(|(id, _)| ids.binary_search(id).is_ok())
+                .map(|(id, positions)| (id, positions.decode()))
+                .collect();
+        }
+    }
+
+    fn doc_freq(&self, key: &str) -> usize {
+        self.terms.get(key).map(|term| term.doc_freq).unwrap_or(0)
+    }
+
+    /// The document frequency of a position of a clause is the one of its most common
+    /// alternative, so the synonyms are not ranked as if they were rarer than the words.
+    fn position_freq(&self, field: Field, terms: &Terms) -> usize {
+        terms.iter().map(|t| self.doc_freq(&field.term_key(t))).max().unwrap_or(0)
+    }
+
+    /// The documents that may match the query according to the postings. `None` means that any
+    /// document may match (e.g. the query consists only of negations).
+    fn candidates(&self, query: &Query) -> Option<BTreeSet<DocId>> {
+        match query {
+            Query::Match { clauses, .. } => {
+                let mut result = BTreeSet::new();
+                for clause in clauses {
+                    // Every position of the phrase must be present. The rarest one is the best filter.
+                    let terms = clause.positions.iter()
+                        .filter(|terms| !terms.is_empty())
+                        .min_by_key(|terms| self.position_freq(clause.field, terms))?;
+                    for t in terms {
+                        if let Some(term) = self.terms.get(clause.field.term_key(t).as_ref()) {
+                            result.extend(term.postings.iter().map(|(id, _)| id));
+                        }
+                    }
+                }
+                Some(result)
+            }
+            // The languages of the documents are not indexed
+            Query::Lang(_) => None,
+            Query::Bool { must, should, must_not, filter } => {
+                if !must.is_empty() {
+                    let mut result: Option<BTreeSet<DocId>> = None;
+                    for q in must {
+                        if let Some(ids) = self.candidates(q) {
+                            result = Some(match result {
+                                Some(result) => result.intersection(&ids).cloned().collect(),
+                                None => ids,
+                            });
+                        }
+                    }
+                    result
+                } else if !should.is_empty() {
+                    let mut result = BTreeSet::new();
+                    for q in should {
+                        result.extend(self.candidates(q)?);
+                    }
+                    Some(result)
+                } else if !must_not.is_empty() || !filter.is_empty() {
+                    None
+                } else {
+                    Some(BTreeSet::new())
+                }
+            }
+        }
+    }
+
+    /// Returns the rank of the document if it matches the query.
+    fn eval_query(&self, query: &Query, id: DocId, doc: &DocInfo) -> Option<f32> {
+        match query {
+            Query::Match { clauses, .. } => self.eval_clauses(clauses, id, &doc.lengths),
+            Query::Lang(language) => (doc.language == Some(*language)).then_some(0f32),
+            Query::Bool { must, should, must_not, filter } => {
+                if must.is_empty() && should.is_empty() && must_not.is_empty() && filter.is_empty() {
+                    return None;
+                }
+                if must_not.iter().any(|q| self.eval_query(q, id, doc).is_some()) {
+                    return None;
+                }
+                if filter.iter().any(|q| self.eval_query(q, id, doc).is_none()) {
+                    return None;
+                }
+                let mut rank = 0f32;
+                for q in must {
+                    rank += self.eval_query(q, id, doc)?;
+                }
+                let mut matched_should = false;
+                for q in should {
+                    if let Some(r) = self.eval_query(q, id, doc) {
+                        rank += r;
+                        matched_should = true;
+                    }
+                }
+                if must.is_empty() && !should.is_empty() && !matched_should {
+                    return None;
+                }
+                Some(rank)
+            }
+        }
+    }
+
+    /// Sums up the boosted ranks of the clauses of the searched fields. Matches if any of them matches.
+    fn eval_clauses(&self, clauses: &[FieldClause], id: DocId, lengths: &FieldLengths) -> Option<f32> {
+        let mut rank = None;
+        for clause in clauses {
+            if let Some(r) = self.eval_field_clause(clause, id, lengths) {
+                *rank.get_or_insert(0f32) += clause.field.boost() * r;
+            }
+        }
+        rank
+    }
+
+    fn eval_field_clause(&self, clause: &FieldClause, id: DocId, lengths: &FieldLengths) -> Option<f32> {
+        let field = clause.field;
+        let f = self.clause_freq(clause, id);
+        if f == 0 {
+            return None;
+        }
+        let dl = lengths[field.index()];
+        let dfs = clause.positions.iter().filter(|terms| !terms.is_empty()).map(|terms| self.position_freq(field, terms));
+        let rank = match self.model.scorer {
+            Scorer::Bm25 { k1, b } => {
+                let idf = dfs.map(|df| compute_bm25_idf(df, self.n)).sum::<f32>();
+                compute_bm25(f, dl, self.avgdl[field.index()], k1, b) * idf
+            }
+            Scorer::TfIdf => {
+                let idf = dfs.map(|df| compute_idf(df, self.n)).sum::<f32>();
+                compute_tf(f, dl) * idf
+            }
+        };
+        Some(rank)
+    }
+
+    fn explain_query(&self, query: &Query, id: DocId, doc: &DocInfo) -> Explanation {
+        let value = self.eval_query(query, id, doc);
+        match query {
+            Query::Match { text, field, clauses } => {
+                let kind = if clauses.iter().any(|clause| clause.positions.len() > 1) { "phrase" } else { "term" };
+                let description = format!("{kind} {prefix}\"{text}\"", prefix = field_prefix(*field));
+                self.explain_clauses(description, clauses, id, &doc.lengths)
+            }
+            Query::Lang(language) => {
+                let detected = doc.language.map(|language| language.name()).unwrap_or("not detected");
+                Explanation::new(format!("language {name} (document: {detected})", name = language.name()), value)
+            }
+            Query::Bool { must, should, must_not, filter } => {
+                let mut explanation = Explanation::new("boolean query", value);
+                for (occur, queries) in [("must", must), ("should", should), ("must not", must_not), ("filter", filter)] {
+                    for q in queries {
+                        let mut detail = self.explain_query(q, id, doc);
+                        detail.description = format!("{occur}: {description}", description = detail.description);
+                        explanation.details.push(detail);
+                    }
+                }
+                explanation
             }
         }
+    }
+
+    fn explain_clauses(&self, description: String, clauses: &[FieldClause], id: DocId, lengths: &FieldLengths) -> Explanation {
+        let mut explanation = Explanation::new(description, self.eval_clauses(clauses, id, lengths));
+        for clause in clauses {
+            let field = clause.field;
+            let rank = self.eval_field_clause(clause, id, lengths);
+            let dl = lengths[field.index()];
+            let avgdl = self.avgdl[field.index()];
+            let terms = clause.positions.iter().map(|terms| if terms.is_empty() { "?".to_string() } else { terms.join("|") }).collect::<Vec<_>>().join(" ");
+            let description = format!("field {name} \"{terms}\" (boost = {boost})", name = field.name(), boost = field.boost());
+            let mut detail = Explanation::new(description, rank.map(|rank| field.boost() * rank));
+            let f = self.clause_freq(clause, id);
+            detail.details.push(Explanation::new(format!("freq (dl = {dl})"), Some(f as f32)));
+            if f > 0 {
+                for terms in clause.positions.iter().filter(|terms| !terms.is_empty()) {
+                    let alternatives = terms.join("|");
+                    let df = self.position_freq(field, terms);
+                    let idf = match self.model.scorer {
+                        Scorer::Bm25 { .. } => compute_bm25_idf(df, self.n),
+                        Scorer::TfIdf => compute_idf(df, self.n),
+                    };
+                    detail.details.push(Explanation::new(format!("idf of \"{alternatives}\" (df = {df}, n = {n})", n = self.n), Some(idf)));
+                }
+                match self.model.scorer {
+                    Scorer::Bm25 { k1, b } => {
+                        let tf = compute_bm25(f, dl, avgdl, k1, b);
+                        detail.details.push(Explanation::new(format!("bm25 tf (k1 = {k1}, b = {b}, avgdl = {avgdl})"), Some(tf)));
+                    }
+                    Scorer::TfIdf => detail.details.push(Explanation::new("tf", Some(compute_tf(f, dl)))),
+                }
+            }
+            explanation.details.push(detail);
+        }
+        explanation
+    }
+
+    fn positions(&self, key: &str, id: DocId) -> Option<&[usize]> {
+        self.terms.get(key)?.positions.get(&id).map(|positions| positions.as_ref())
+    }
+
+    /// Positions of any of the terms in the document in the ascending order.
+    fn merged_positions(&self, field: Field, terms: &Terms, id: DocId) -> Cow<'_, [usize]> {
+        let mut found = terms.iter().filter_map(|t| self.positions(&field.term_key(t), id));
+        let Some(first) = found.next() else {
+            return Cow::Borrowed(&[]);
+        };
+        let mut rest = found.peekable();
+        if rest.peek().is_none() {
+            return Cow::Borrowed(first);
+        }
+        let mut merged = first.to_vec();
+        rest.for_each(|positions| merged.extend(positions));
+        merged.sort();
+        merged.dedup();
+        Cow::Owned(merged)
+    }
 
-        self.docs.insert(file_path, Doc {count, tf, last_modified});
+    /// How many times the clause occurs in the document.
+    fn clause_freq(&self, clause: &FieldClause, id: DocId) -> usize {
+        let mut positions = Vec::with_capacity(clause.positions.len());
+        for (offset, terms) in clause.positions.iter().enumerate() {
+            // The gap of a dropped word matches any word
+            if terms.is_empty() {
+                continue;
+            }
+            let merged = self.merged_positions(clause.field, terms, id);
+            if merged.is_empty() {
+                return 0;
+            }
+            positions.push((offset, merged));
+        }
+        match positions.as_slice() {
+            [] => 0,
+            [(_, single)] => single.len(),
+            [(first, starts), rest @ ..] => {
+                starts.iter().filter(|&&start| {
+                    rest.iter().all(|(offset, positions)| {
+                        (start + offset).checked_sub(*first).is_some_and(|position| positions.binary_search(&position).is_ok())
+                    })
+                }).count()
+            }
+        }
     }
 }
 
-fn compute_tf(t: &str, doc: &Doc) -> f32 {
-    let n = doc.count as f32;
-    let m = doc.tf.get(t).cloned().unwrap_or(0) as f32;
+fn field_prefix(field: Option<Field>) -> String {
+    field.map(|field| format!("{name}:", name = field.name())).unwrap_or_default()
+}
+
+fn compute_tf(f: usize, dl: usize) -> f32 {
+    let n = dl as f32;
+    let m = f as f32;
     m / n
 }
 
-fn compute_idf(t: &str, n: usize, df: &DocFreq) -> f32 {
+fn compute_idf(df: usize, n: usize) -> f32 {
     let n = n as f32;
-    let m = df.get(t).cloned().unwrap_or(1) as f32;
+    let m = df.max(1) as f32;
     (n / m).log10()
 }
+
+fn compute_bm25_idf(df: usize, n: usize) -> f32 {
+    let n = n as f32;
+    let m = df as f32;
+    // The "plus one" variant of the IDF is always positive, even for the terms that occur in
+    // more than half of the documents.
+    (1f32 + (n - m + 0.5) / (m + 0.5)).ln()
+}
+
+fn compute_bm25(f: usize, dl: usize, avgdl: f32, k1: f32, b: f32) -> f32 {
+    let f = f as f32;
+    let dl = dl as f32;
+    let norm = if avgdl > 0f32 { dl / avgdl } else { 1f32 };
+    f * (k1 + 1f32) / (f + k1 * (1f32 - b + b * norm))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::markdown;
+    use crate::query;
+
+    /// The paths of the matching documents, the best matches first.
+    pub(crate) fn search(model: &Model, query: &str) -> Vec<String> {
+        let query = query::parse(&query.chars().collect::<Vec<_>>(), model.analyzers(), &model.languages()).unwrap();
+        model.search_query(&query).into_iter().map(|(id, _)| model.doc(id).unwrap().path.display().to_string()).collect()
+    }
+
+    pub(crate) fn insert(model: &mut Model, file_path: &str, body: &str) {
+        let doc = AnalyzedDoc::new(ParsedDoc::from_body(body.to_string()), None, model.analyzers());
+        model.insert_document(PathBuf::from(file_path), SystemTime::UNIX_EPOCH + Duration::from_secs(1700000000), doc);
+    }
+
+    pub(crate) fn sample_model() -> Model {
+        let mut model = Model::default();
+        insert(&mut model, "/docs/fox.txt", "The quick brown fox jumps over the lazy dog");
+        insert(&mut model, "/docs/dog.txt", "A lazy dog sleeps all day, the dog is brown");
+        insert(&mut model, "/docs/cat.txt", "Cats ignore the dogs and the foxes");
+        model
+    }
+
+    /// The JSON index as the versions before the binary index file wrote it.
+    const LEGACY_INDEX: &str = r#"{
+        "docs": {
+            "/docs/fox.txt": {
+                "tf": {"the": 1, "quick": 1, "brown": 1, "fox": 1},
+                "count": 4,
+                "last_modified": {"secs_since_epoch": 1700000000, "nanos_since_epoch": 0}
+            },
+            "/docs/dog.txt": {
+                "tf": {"fox": 1, "brown": 2, "dog": 3, ".": 1},
+                "count": 7,
+                "last_modified": {"secs_since_epoch": 1700000000, "nanos_since_epoch": 0}
+            }
+        },
+        "df": {"the": 1, "quick": 1, "brown": 2, "fox": 2, "dog": 1, ".": 1}
+    }"#;
+
+    #[test]
+    fn migrate_legacy_json() {
+        let mut model = Model::from_legacy_json(LEGACY_INDEX.as_bytes(), Path::new("/docs"), Path::new("/docs")).unwrap();
+        assert_eq!(model.docs_count(), 2);
+        assert_eq!(model.average_field_len(Field::Body), 5.5);
+        assert_eq!(search(&model, "dog"), ["/docs/dog.txt"]);
+        assert_eq!(search(&model, "brown").len(), 2);
+        // There are no positions to match the phrases against until the documents are reindexed
+        assert!(search(&model, "\"brown fox\"").is_empty());
+        // Even if the files have not changed since
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1700000000);
+        assert!(model.requires_reindexing(Path::new("/docs/fox.txt"), last_modified));
+        assert!(model.requires_reindexing(Path::new("/docs/dog.txt"), last_modified));
+    }
+
+    #[test]
+    fn migrate_relative_paths() {
+        let legacy = r#"{
+            "docs": {
+                "docs/fox.txt": {"tf": {"fox": 1}, "count": 1, "last_modified": {"secs_since_epoch": 0, "nanos_since_epoch": 0}},
+                "notes/dog.txt": {"tf": {"dog": 1}, "count": 1, "last_modified": {"secs_since_epoch": 0, "nanos_since_epoch": 0}}
+            },
+            "df": {"fox": 1, "dog": 1}
+        }"#;
+        let model = Model::from_legacy_json(legacy.as_bytes(), Path::new("docs"), Path::new("/home/user/docs")).unwrap();
+        assert_eq!(search(&model, "fox"), ["/home/user/docs/fox.txt"]);
+        // Not in the folder, so it is left for the removal of the stale documents
+        assert_eq!(search(&model, "dog"), ["notes/dog.txt"]);
+    }
+
+    #[test]
+    fn reject_unknown_json() {
+        assert!(Model::from_legacy_json(r#"{"docs": {"0": {"path": "/docs/fox.txt"}}}"#.as_bytes(), Path::new("/docs"), Path::new("/docs")).is_err());
+    }
+
+    #[test]
+    fn field_keys_do_not_clash() {
+        let mut model = Model::default();
+        let last_modified = SystemTime::UNIX_EPOCH;
+        // U+FF1A FULLWIDTH COLON does not split the word and becomes `:` after NFKC
+        let body_only = ParsedDoc::from_body("title\u{ff1a}rust and some\u{0}1rust".to_string());
+        model.insert_document(PathBuf::from("/docs/body.txt"), last_modified, AnalyzedDoc::new(body_only, None, &FieldAnalyzers::default()));
+        let titled = ParsedDoc { title: "Rust".to_string(), ..Default::default() };
+        model.insert_document(PathBuf::from("/docs/title.txt"), last_modified, AnalyzedDoc::new(titled, None, &FieldAnalyzers::default()));
+
+        assert_eq!(search(&model, "title:rust"), ["/docs/title.txt"]);
+        assert_eq!(search(&model, "title\u{ff1a}rust"), ["/docs/body.txt"]);
+        for key in model.terms_from("") {
+            let (field, term) = Field::parse_term_key(key);
+            assert_eq!(field.term_key(term), key);
+            assert!(!term.contains('\u{0}'), "{key:?}");
+        }
+        assert_eq!(Field::parse_term_key("title:rust"), (Field::Body, "title:rust"));
+        assert_eq!(Field::parse_term_key(&Field::Headings.term_key("rust")), (Field::Headings, "rust"));
+    }
+
+    #[test]
+    fn shorter_documents_rank_higher() {
+        let mut model = Model::default();
+        insert(&mut model, "/docs/long.txt", "rust is a language with a long list of features that go on and on for quite a while");
+        insert(&mut model, "/docs/short.txt", "rust language");
+        insert(&mut model, "/docs/other.txt", "python is a language too");
+        for scorer in [Scorer::default(), Scorer::Bm25 { k1: 2.0, b: 1.0 }, Scorer::TfIdf] {
+            model.scorer = scorer;
+            assert_eq!(search(&model, "rust"), ["/docs/short.txt", "/docs/long.txt"], "{scorer:?}");
+            // The rarer term weighs more
+            assert_eq!(search(&model, "rust OR language")[0], "/docs/short.txt", "{scorer:?}");
+        }
+        // Without the length normalization only the frequency of the term matters
+        model.scorer = Scorer::Bm25 { k1: 1.2, b: 0.0 };
+        let query = query::parse(&"rust".chars().collect::<Vec<_>>(), model.analyzers(), &[]).unwrap();
+        let ranks = model.search_query(&query);
+        assert_eq!(ranks.len(), 2);
+        assert_eq!(ranks[0].1, ranks[1].1);
+    }
+
+    #[test]
+    fn bm25_math() {
+        // The frequency saturates at k1 + 1
+        assert_eq!(compute_bm25(1, 10, 10.0, 1.2, 0.75), 1.0);
+        assert!(compute_bm25(1000, 10, 10.0, 1.2, 0.75) < 2.2);
+        assert!(compute_bm25(2, 10, 10.0, 1.2, 0.75) > compute_bm25(1, 10, 10.0, 1.2, 0.75));
+        assert!(compute_bm25(1, 5, 10.0, 1.2, 0.75) > compute_bm25(1, 20, 10.0, 1.2, 0.75));
+        // Positive even for the terms of every document
+        assert!(compute_bm25_idf(10, 10) > 0.0);
+        assert!(compute_bm25_idf(1, 10) > compute_bm25_idf(5, 10));
+        assert_eq!(compute_idf(1, 10), 1.0);
+        assert_eq!(compute_tf(1, 4), 0.25);
+    }
+
+    #[test]
+    fn stop_words_leave_gaps() {
+        let mut model = Model::default();
+        let analyzer = Analyzer::parse("words,lowercase,stop-words:english").unwrap();
+        model.set_analyzers(Field::ALL.map(|_| analyzer.clone()));
+        insert(&mut model, "/docs/gap.txt", "Memory and safety");
+        insert(&mut model, "/docs/adjacent.txt", "memory safety first");
+        insert(&mut model, "/docs/reversed.txt", "safety of the memory");
+        assert_eq!(search(&model, "\"memory safety\""), ["/docs/adjacent.txt"]);
+        assert_eq!(search(&model, "\"memory and safety\""), ["/docs/gap.txt"]);
+        assert_eq!(search(&model, "\"safety of the memory\""), ["/docs/reversed.txt"]);
+        assert!(search(&model, "\"safety memory\"").is_empty());
+        // The stop words at the ends of the phrases don't matter
+        assert_eq!(search(&model, "\"the memory safety\""), ["/docs/adjacent.txt"]);
+        // Nor do they make the documents longer
+        let id = model.find_doc(Path::new("/docs/gap.txt")).unwrap();
+        assert_eq!(model.doc(id).unwrap().lengths[Field::Body.index()], 2);
+        assert_eq!(snippets(&model, "\"memory and safety\""), [["[Memory] and [safety]"]]);
+    }
+
+    /// The rendered snippets of the documents of the query.
+    pub(crate) fn snippets(model: &Model, query: &str) -> Vec<Vec<String>> {
+        let query = query::parse(&query.chars().collect::<Vec<_>>(), model.analyzers(), &model.languages()).unwrap();
+        let ids = model.search_query(&query).into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        model.snippets(&ids, &query).into_iter().map(|draft| draft.finish().iter().map(|snippet| snippet.render("[", "]")).collect()).collect()
+    }
+
+    #[test]
+    fn snippets_of_matches() {
+        let mut model = Model::default();
+        insert(&mut model, "/docs/a.txt", "Ünïcode   text\n\nabout the Rust   language. Nothing else here.");
+        let long = (0..100).map(|i| if i % 40 == 10 { "rust".to_string() } else { format!("w{i}") }).collect::<Vec<_>>().join(" ");
+        insert(&mut model, "/docs/b.txt", &long);
+        let result = snippets(&model, "rust languages");
+        assert_eq!(result[0], ["Ünïcode text about the [Rust] [language]. Nothing else here"]);
+        assert_eq!(result[1], [
+            "w4 w5 w6 w7 w8 w9 [rust] w11 w12 w13 w14 w15 w16 w17 w18 w19 w20 w21 w22 w23 w24 w25 w26 w27",
+            "w44 w45 w46 w47 w48 w49 [rust] w51 w52 w53 w54 w55 w56 w57 w58 w59 w60 w61 w62 w63 w64 w65 w66 w67",
+            "w84 w85 w86 w87 w88 w89 [rust] w91 w92 w93 w94 w95 w96 w97 w98 w99",
+        ]);
+        // The beginning of the text if nothing matched
+        let mut result = snippets(&model, "-fox");
+        result.sort();
+        assert_eq!(result, [
+            vec!["w0 w1 w2 w3 w4 w5 w6 w7 w8 w9 rust w11 w12 w13 w14 w15 w16 w17 w18 w19 w20 w21 w22 w23".to_string()],
+            vec!["Ünïcode text about the Rust language. Nothing else here".to_string()],
+        ]);
+    }
+
+    #[test]
+    fn code_field() {
+        let mut model = Model::default();
+        for (file_path, source) in [("/docs/code.md", "Call `parse` first"), ("/docs/prose.md", "We parse")] {
+            let doc = AnalyzedDoc::new(markdown::parse(source), None, &FieldAnalyzers::default());
+            model.insert_document(PathBuf::from(file_path), SystemTime::UNIX_EPOCH, doc);
+        }
+        assert_eq!(search(&model, "code:parse"), ["/docs/code.md"]);
+        // The shorter body loses to the match in the code
+        assert_eq!(search(&model, "parse"), ["/docs/code.md", "/docs/prose.md"]);
+        assert_eq!(search(&model, "body:parse"), ["/docs/prose.md", "/docs/code.md"]);
+    }
+
+    #[test]
+    fn languages_of_documents() {
+        let mut model = Model::default();
+        for (file_path, body, language) in [
+            ("/docs/de.txt", "Die alten Häuser der Stadt stehen am Fluss", Language::German),
+            ("/docs/en.txt", "The quick foxes run past the old houses", Language::English),
+        ] {
+            let doc = AnalyzedDoc::new(ParsedDoc::from_body(body.to_string()), Some(language), model.analyzers());
+            model.insert_document(PathBuf::from(file_path), SystemTime::UNIX_EPOCH, doc);
+        }
+        assert_eq!(model.languages(), [Language::English, Language::German]);
+
+        // The words of a document are stemmed in its language, the query in all of them
+        assert_eq!(search(&model, "Häuser"), ["/docs/de.txt"]);
+        assert_eq!(search(&model, "haus"), ["/docs/de.txt"]);
+        assert_eq!(search(&model, "fox"), ["/docs/en.txt"]);
+        assert_eq!(search(&model, "house"), ["/docs/en.txt"]);
+
+        assert_eq!(search(&model, "lang:de"), ["/docs/de.txt"]);
+        assert_eq!(search(&model, "-lang:de"), ["/docs/en.txt"]);
+        assert_eq!(search(&model, "lang:english"), ["/docs/en.txt"]);
+        assert!(search(&model, "fox lang:de").is_empty());
+        assert_eq!(search(&model, "fox -lang:de"), ["/docs/en.txt"]);
+        let mut both = search(&model, "lang:de OR lang:en");
+        both.sort();
+        assert_eq!(both, ["/docs/de.txt", "/docs/en.txt"]);
+    }
+}
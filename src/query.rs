@@ -0,0 +1,473 @@
+use std::fmt;
+use super::analyzer::{FieldAnalyzers, Stemming, Terms};
+use super::language::Language;
+use super::model::Field;
+
+// Grammar of the queries:
+//
+// query    := sequence
+// sequence := and ( "OR"? and )*      -- juxtaposition means OR
+// and      := unary ( "AND" unary )*
+// unary    := ( "NOT" | "-" | "+" ) primary | primary
+// primary  := field? ( word | "\"" phrase "\"" ) | filter | "(" sequence ")"
+// field    := ( "body" | "title" | "headings" | "code" ) ":"
+// filter   := "lang:" language                -- required unless negated, several of them in a
+//                                              -- sequence mean any of them
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// A word or a phrase as it was typed. The field restricts the match to one field of the
+    /// document. Otherwise all of them are searched.
+    Match { text: String, field: Option<Field>, clauses: Vec<FieldClause> },
+    /// The documents of the language. Does not contribute to the rank.
+    Lang(Language),
+    /// A document matches if it matches all of `must` and `filter`, none of `must_not` and at
+    /// least one of `should` (unless `must` is not empty or `should` is empty). Only `must` and
+    /// `should` contribute to the rank.
+    Bool {
+        must: Vec<Query>,
+        should: Vec<Query>,
+        must_not: Vec<Query>,
+        filter: Vec<Query>,
+    },
+}
+
+/// The text of a match analyzed for one of the searched fields. The fields may have different
+/// analyzers, so they get the terms of their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldClause {
+    pub field: Field,
+    /// A single position is a term, several of them are a phrase: the terms that must appear in
+    /// the document adjacently and in order. Any of the alternatives counts at each position. The
+    /// positions of the dropped words are empty, they never come first or last.
+    pub positions: Vec<Terms>,
+}
+
+impl Query {
+    /// Whether the query matches nothing because it has no terms.
+    fn is_empty(&self) -> bool {
+        matches!(self, Query::Bool { must, should, must_not, filter } if must.is_empty() && should.is_empty() && must_not.is_empty() && filter.is_empty())
+    }
+
+    /// Whether the query narrows the results down rather than finds them.
+    fn is_filter(&self) -> bool {
+        match self {
+            Query::Lang(_) => true,
+            Query::Bool { must, should, must_not, filter } => must.is_empty() && should.is_empty() && must_not.is_empty() && !filter.is_empty(),
+            Query::Match { .. } => false,
+        }
+    }
+
+    /// All the clauses of the query including the negated ones.
+    pub fn clauses(&self) -> Vec<&FieldClause> {
+        let mut result = Vec::new();
+        self.collect_clauses(&mut result);
+        result
+    }
+
+    /// The texts that contribute to the matches, i.e. the negated ones excluded.
+    pub fn matching_texts(&self) -> Vec<&str> {
+        let mut result = Vec::new();
+        self.collect_matching_texts(&mut result);
+        result
+    }
+
+    fn collect_clauses<'a>(&'a self, result: &mut Vec<&'a FieldClause>) {
+        match self {
+            Query::Match { clauses, .. } => result.extend(clauses),
+            Query::Lang(_) => {}
+            Query::Bool { must, should, must_not, filter } => {
+                for query in must.iter().chain(should).chain(must_not).chain(filter) {
+                    query.collect_clauses(result);
+                }
+            }
+        }
+    }
+
+    fn collect_matching_texts<'a>(&'a self, result: &mut Vec<&'a str>) {
+        match self {
+            Query::Match { text, .. } => result.push(text),
+            Query::Lang(_) => {}
+            Query::Bool { must, should, .. } => {
+                for query in must.iter().chain(should) {
+                    query.collect_matching_texts(result);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryError {
+    pub message: String,
+    /// Offset of the problem in the query counted in chars.
+    pub offset: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{message} at position {offset}", message = self.message, offset = self.offset)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Occur {
+    Must,
+    Should,
+    MustNot,
+    Filter,
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    Field(Field),
+    Lang(String),
+    Word(String),
+    Phrase(String),
+    OpenParen,
+    CloseParen,
+    Plus,
+    Minus,
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    offset: usize,
+}
+
+fn tokenize(query: &[char]) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < query.len() {
+        let offset = i;
+        let c = query[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if let Some((name, len)) = prefix(&query[i..]) {
+            if name == "lang" {
+                i += len;
+                let start = i;
+                while i < query.len() && !query[i].is_whitespace() && !"()\"".contains(query[i]) {
+                    i += 1;
+                }
+                tokens.push(Token { kind: TokenKind::Lang(query[start..i].iter().collect()), offset });
+                continue;
+            }
+            if let Some(field) = Field::from_name(&name) {
+                i += len;
+                tokens.push(Token { kind: TokenKind::Field(field), offset });
+                continue;
+            }
+        }
+
+        let kind = match c {
+            '(' => { i += 1; TokenKind::OpenParen }
+            ')' => { i += 1; TokenKind::CloseParen }
+            '"' => {
+                i += 1;
+                let start = i;
+                while i < query.len() && query[i] != '"' {
+                    i += 1;
+                }
+                if i >= query.len() {
+                    return Err(QueryError {
+                        message: "unclosed quote".to_string(),
+                        offset,
+                    });
+                }
+                let phrase = query[start..i].iter().collect();
+                i += 1;
+                TokenKind::Phrase(phrase)
+            }
+            // The modifiers only count at the beginning of a word so things like `e-mail` stay intact
+            '+' | '-' if i + 1 < query.len() && !query[i + 1].is_whitespace() => {
+                i += 1;
+                if c == '+' { TokenKind::Plus } else { TokenKind::Minus }
+            }
+            _ => {
+                let start = i;
+                while i < query.len() && !query[i].is_whitespace() && !"()\"".contains(query[i]) {
+                    i += 1;
+                }
+                let word = query[start..i].iter().collect::<String>();
+                match word.as_str() {
+                    "AND" => TokenKind::And,
+                    "OR" => TokenKind::Or,
+                    "NOT" => TokenKind::Not,
+                    _ => TokenKind::Word(word),
+                }
+            }
+        };
+        tokens.push(Token { kind, offset });
+    }
+    Ok(tokens)
+}
+
+/// Recognizes `name:` followed by a word or a phrase (`title:`, `lang:`). Returns the name and
+/// the length of the prefix.
+fn prefix(query: &[char]) -> Option<(String, usize)> {
+    let colon = query.iter().take_while(|c| c.is_alphabetic()).count();
+    if query.get(colon) != Some(&':') || query.get(colon + 1).map(|c| c.is_whitespace() || "()".contains(*c)).unwrap_or(true) {
+        return None;
+    }
+    Some((query[..colon].iter().collect(), colon + 1))
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    cursor: usize,
+    end: usize,
+    analyzers: &'a FieldAnalyzers,
+    languages: &'a [Language],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.cursor).map(|token| &token.kind)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.cursor).map(|token| token.offset).unwrap_or(self.end)
+    }
+
+    fn error(&self, message: &str) -> QueryError {
+        QueryError {
+            message: message.to_string(),
+            offset: self.offset(),
+        }
+    }
+
+    fn parse_sequence(&mut self) -> Result<Query, QueryError> {
+        let mut group = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(TokenKind::CloseParen) => break,
+                Some(TokenKind::Or) if group.is_empty() => return Err(self.error("OR without left operand")),
+                Some(TokenKind::Or) => {
+                    self.cursor += 1;
+                    if matches!(self.peek(), None | Some(TokenKind::CloseParen) | Some(TokenKind::Or)) {
+                        return Err(self.error("OR without right operand"));
+                    }
+                }
+                _ => group.push(self.parse_and()?),
+            }
+        }
+        Ok(make_bool(group))
+    }
+
+    fn parse_and(&mut self) -> Result<(Occur, Query), QueryError> {
+        let mut group = vec![self.parse_unary()?];
+        while let Some(TokenKind::And) = self.peek() {
+            self.cursor += 1;
+            let (occur, query) = self.parse_unary()?;
+            group.push(if occur == Occur::MustNot { (occur, query) } else { (Occur::Must, query) });
+        }
+        if group.len() == 1 {
+            return Ok(group.pop().unwrap());
+        }
+        if let Some(first) = group.first_mut() {
+            if first.0 == Occur::Should {
+                first.0 = Occur::Must;
+            }
+        }
+        Ok((Occur::Should, make_bool(group)))
+    }
+
+    fn parse_unary(&mut self) -> Result<(Occur, Query), QueryError> {
+        let occur = match self.peek() {
+            Some(TokenKind::Not) | Some(TokenKind::Minus) => Occur::MustNot,
+            Some(TokenKind::Plus) => Occur::Must,
+            _ => Occur::Should,
+        };
+        if occur != Occur::Should {
+            self.cursor += 1;
+        }
+        let query = self.parse_primary()?;
+        if query.is_filter() && occur != Occur::MustNot {
+            return Ok((Occur::Filter, query));
+        }
+        Ok((occur, query))
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, QueryError> {
+        let field = match self.peek() {
+            Some(TokenKind::Field(field)) => {
+                let field = *field;
+                self.cursor += 1;
+                if !matches!(self.peek(), Some(TokenKind::Word(_)) | Some(TokenKind::Phrase(_))) {
+                    return Err(self.error("expected a term or a phrase after the field"));
+                }
+                Some(field)
+            }
+            _ => None,
+        };
+        let query = match self.peek() {
+            Some(TokenKind::Word(text)) | Some(TokenKind::Phrase(text)) => {
+                let clauses = Field::ALL
+                    .into_iter()
+                    .filter(|f| field.is_none() || field == Some(*f))
+                    .map(|field| {
+                        let mut positions = self.analyzers[field.index()]
+                            .analyze_with(text, Stemming::Also(self.languages))
+                            .map(|(terms, _)| terms)
+                            .collect::<Vec<_>>();
+                        // Only the gaps between the words matter
+                        let end = positions.iter().rposition(|terms| !terms.is_empty()).map_or(0, |last| last + 1);
+                        positions.truncate(end);
+                        let start = positions.iter().position(|terms| !terms.is_empty()).unwrap_or(0);
+                        positions.drain(..start);
+                        FieldClause { field, positions }
+                    })
+                    // The analyzer of the field throws the text away (punctuation, stop words)
+                    .filter(|clause| !clause.positions.is_empty())
+                    .collect::<Vec<_>>();
+                if clauses.is_empty() {
+                    // Dropped by `make_bool` unless there is nothing else
+                    make_bool(vec![])
+                } else {
+                    Query::Match { text: text.clone(), field, clauses }
+                }
+            }
+            Some(TokenKind::Lang(name)) => match Language::from_name(name) {
+                Some(language) => Query::Lang(language),
+                None => return Err(self.error(&format!("unknown language {name}"))),
+            },
+            Some(TokenKind::OpenParen) => {
+                let offset = self.offset();
+                self.cursor += 1;
+                let query = self.parse_sequence()?;
+                if self.peek() != Some(&TokenKind::CloseParen) {
+                    return Err(QueryError {
+                        message: "unclosed parenthesis".to_string(),
+                        offset,
+                    });
+                }
+                query
+            }
+            Some(TokenKind::CloseParen) => return Err(self.error("unexpected closing parenthesis")),
+            Some(TokenKind::And) => return Err(self.error("AND without left operand")),
+            Some(TokenKind::Or) => return Err(self.error("OR without left operand")),
+            Some(_) | None => return Err(self.error("expected a term")),
+        };
+        self.cursor += 1;
+        Ok(query)
+    }
+}
+
+fn make_bool(group: Vec<(Occur, Query)>) -> Query {
+    let group = group.into_iter().filter(|(_, query)| !query.is_empty()).collect::<Vec<_>>();
+    if group.len() == 1 && group[0].0 != Occur::MustNot {
+        return group.into_iter().next().unwrap().1;
+    }
+    let mut must = Vec::new();
+    let mut should = Vec::new();
+    let mut must_not = Vec::new();
+    let mut filter = Vec::new();
+    for (occur, query) in group {
+        match occur {
+            Occur::Must => must.push(query),
+            Occur::Should => should.push(query),
+            Occur::MustNot => must_not.push(query),
+            Occur::Filter => filter.push(query),
+        }
+    }
+    if filter.len() > 1 {
+        // `lang:de lang:fr` means any of the languages
+        filter = vec![Query::Bool { must: vec![], should: filter, must_not: vec![], filter: vec![] }];
+    }
+    Query::Bool { must, should, must_not, filter }
+}
+
+/// The words of the query are analyzed with the same analyzers as the fields of the documents and
+/// stemmed in all the languages of the documents.
+pub fn parse(query: &[char], analyzers: &FieldAnalyzers, languages: &[Language]) -> Result<Query, QueryError> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        cursor: 0,
+        end: query.len(),
+        analyzers,
+        languages,
+    };
+    let result = parser.parse_sequence()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected closing parenthesis"));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compact form of the query: `+` must, `-` must not, `#` filter, nothing for should.
+    fn show(query: &Query) -> String {
+        match query {
+            Query::Match { text, field, .. } => format!("{prefix}{text}", prefix = field.map(|field| format!("{name}:", name = field.name())).unwrap_or_default()),
+            Query::Lang(language) => format!("lang:{code}", code = language.code()),
+            Query::Bool { must, should, must_not, filter } => {
+                let parts = must.iter().map(|q| format!("+{q}", q = show(q)))
+                    .chain(should.iter().map(show))
+                    .chain(must_not.iter().map(|q| format!("-{q}", q = show(q))))
+                    .chain(filter.iter().map(|q| format!("#{q}", q = show(q))))
+                    .collect::<Vec<_>>();
+                format!("({parts})", parts = parts.join(" "))
+            }
+        }
+    }
+
+    fn parse_str(query: &str) -> Result<Query, QueryError> {
+        parse(&query.chars().collect::<Vec<_>>(), &FieldAnalyzers::default(), &[])
+    }
+
+    #[test]
+    fn precedence() {
+        let cases = [
+            ("cat", "cat"),
+            ("cat dog", "(cat dog)"),
+            ("cat OR dog", "(cat dog)"),
+            ("cat OR dog AND fish", "(cat (+dog +fish))"),
+            ("cat AND dog fish", "((+cat +dog) fish)"),
+            ("-cat AND dog", "(+dog -cat)"),
+            ("+cat dog", "(+cat dog)"),
+            ("(cat OR dog) -fish", "((cat dog) -fish)"),
+            ("NOT cat NOT dog", "(-cat -dog)"),
+            ("NOT cat", "(-cat)"),
+            ("title:cat \"big dog\"", "(title:cat big dog)"),
+            ("e-mail", "e-mail"),
+            ("lang:de", "lang:de"),
+            ("cat lang:de", "(cat #lang:de)"),
+            ("lang:de lang:fr", "(#(lang:de lang:fr))"),
+            ("cat -lang:german", "(cat -lang:de)"),
+        ];
+        for (query, expected) in cases {
+            let result = parse_str(query).unwrap_or_else(|err| panic!("{query}: {err}"));
+            assert_eq!(show(&result), expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("cat \"big dog", "unclosed quote", 4),
+            ("cat (dog OR fish", "unclosed parenthesis", 4),
+            ("cat dog)", "unexpected closing parenthesis", 7),
+            ("OR cat", "OR without left operand", 0),
+            ("cat OR", "OR without right operand", 6),
+            ("cat AND", "expected a term", 7),
+            ("title:-cat", "expected a term or a phrase after the field", 6),
+            ("lang:xx", "unknown language xx", 0),
+        ];
+        for (query, message, offset) in cases {
+            match parse_str(query) {
+                Ok(result) => panic!("{query}: expected an error, got {result}", result = show(&result)),
+                Err(err) => assert_eq!((err.message.as_str(), err.offset), (message, offset), "{query}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::{Instant, UNIX_EPOCH};
+
+use super::model::*;
+use super::query;
+
+/// How many results of a query are shown.
+const RESULTS_LIMIT: usize = 10;
+/// How many terms `:terms` shows at most.
+const TERMS_LIMIT: usize = 50;
+/// How many chars of the text of the document `:doc` shows.
+const PREVIEW_LEN: usize = 200;
+
+fn help() {
+    println!("Type a query to search the index or one of the commands:");
+    println!("    :explain <doc> <query>    explain the rank of the document (path or id) for the query");
+    println!("    :terms [field:]<prefix>   list the terms that start with the prefix");
+    println!("    :doc <doc>                show the document (path or id)");
+    println!("    :stats                    show the statistics of the index");
+    println!("    :help                     show this help");
+    println!("    :quit                     exit the REPL");
+}
+
+/// Accepts a path relative to the folder as well as the one the document was indexed with.
+fn find_doc(model: &mut Model, dir_path: &Path, doc: &str) -> Option<DocId> {
+    if let Ok(id) = doc.parse::<DocId>() {
+        return model.doc(id).map(|_| id);
+    }
+    model.find_doc(Path::new(doc)).or_else(|| model.find_doc(&dir_path.join(doc)))
+}
+
+fn parse_query(model: &Model, query: &str) -> Option<query::Query> {
+    query::parse(&query.chars().collect::<Vec<_>>(), model.analyzers(), &model.languages()).map_err(|err| {
+        println!("ERROR: invalid query: {err}");
+    }).ok()
+}
+
+fn print_explanation(explanation: &Explanation, depth: usize) {
+    let value = match explanation.value {
+        Some(value) => format!("{value:.4}"),
+        None => "-".to_string(),
+    };
+    println!("{value:>10}  {indent}{description}", indent = "  ".repeat(depth), description = explanation.description);
+    for detail in &explanation.details {
+        print_explanation(detail, depth + 1);
+    }
+}
+
+fn search(model: &Model, query: &str) {
+    let Some(query) = parse_query(model, query) else {
+        return;
+    };
+    let started = Instant::now();
+    let matches = model.search_query(&query);
+    let elapsed = started.elapsed();
+    let top = &matches[..matches.len().min(RESULTS_LIMIT)];
+    let ids = top.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    for ((id, score), snippets) in top.iter().zip(model.snippets(&ids, &query)) {
+        if let Some(doc) = model.doc(*id) {
+            println!("{score:>10.4}  [{id}] {path}", path = doc.path.display());
+        }
+        if let Some(snippet) = snippets.finish().first() {
+            println!("{:>10}  {snippet}", "", snippet = snippet.render("[", "]"));
+        }
+    }
+    println!("{total} results in {ms:.2} ms", total = matches.len(), ms = elapsed.as_secs_f64()*1000.0);
+}
+
+fn explain(model: &mut Model, dir_path: &Path, args: &str) {
+    let Some((doc, query)) = args.split_once(char::is_whitespace) else {
+        println!("ERROR: usage: :explain <doc> <query>");
+        return;
+    };
+    let Some(id) = find_doc(model, dir_path, doc) else {
+        println!("ERROR: no such document: {doc}");
+        return;
+    };
+    let Some(query) = parse_query(model, query) else {
+        return;
+    };
+    if let Some(explanation) = model.explain(id, &query) {
+        print_explanation(&explanation, 0);
+    }
+}
+
+fn terms(model: &Model, prefix: &str) {
+    // The terms are stored lowercase
+    let prefix = prefix.to_lowercase();
+    // `title:ru` lists the terms of the title
+    let prefix = match prefix.split_once(':').and_then(|(name, rest)| Some((Field::from_name(name)?, rest))) {
+        Some((field, rest)) => field.term_key(rest).into_owned(),
+        None => prefix,
+    };
+    // The terms whose documents were all removed are skipped
+    let mut matched = model.terms_from(&prefix)
+        .take_while(|key| key.starts_with(&prefix))
+        .map(|key| (key, model.term_postings(key).doc_freq()))
+        .filter(|(_, df)| *df > 0);
+    for (key, df) in matched.by_ref().take(TERMS_LIMIT) {
+        let (field, term) = Field::parse_term_key(key);
+        println!("{df:>10}  {field:<8}  {term}", field = field.name());
+    }
+    let more = matched.count();
+    if more > 0 {
+        println!("... and {more} more");
+    }
+}
+
+fn doc(model: &mut Model, dir_path: &Path, doc: &str) {
+    let Some(id) = find_doc(model, dir_path, doc) else {
+        println!("ERROR: no such document: {doc}");
+        return;
+    };
+    let Some(doc) = model.doc(id) else {
+        return;
+    };
+    let last_modified = doc.last_modified.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+    println!("id:            {id}");
+    println!("path:          {path}", path = doc.path.display());
+    let lengths = Field::ALL.map(|field| format!("{name} {length}", name = field.name(), length = doc.lengths[field.index()]));
+    println!("lengths:       {lengths} terms", lengths = lengths.join(", "));
+    println!("language:      {language}", language = doc.language.map(|language| language.name()).unwrap_or("not detected"));
+    println!("last modified: {last_modified} (seconds since UNIX epoch)");
+    let preview = doc.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut chars = preview.chars();
+    let head = chars.by_ref().take(PREVIEW_LEN).collect::<String>();
+    let ellipsis = if chars.next().is_some() { "..." } else { "" };
+    println!("text:          {head}{ellipsis}");
+}
+
+fn stats(model: &Model) {
+    println!("docs:           {docs}", docs = model.docs_count());
+    println!("terms:          {terms}", terms = model.terms_count());
+    let avgdl = Field::ALL.map(|field| format!("{name} {avgdl:.2}", name = field.name(), avgdl = model.average_field_len(field)));
+    println!("avg lengths:    {avgdl}", avgdl = avgdl.join(", "));
+    let languages = model.languages().into_iter().map(|language| format!("{name} {count}", name = language.name(), count = model.language_count(language)));
+    println!("languages:      {languages}", languages = languages.collect::<Vec<_>>().join(", "));
+    println!("scorer:         {scorer:?}", scorer = model.scorer);
+    println!("analyzers:");
+    for field in Field::ALL {
+        println!("    {name:<12}{analyzer}", name = field.name(), analyzer = model.analyzers()[field.index()]);
+    }
+}
+
+pub fn start(dir_path: &Path, model: &mut Model) -> Result<(), ()> {
+    help();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().map_err(|err| {
+            eprintln!("ERROR: could not write the prompt: {err}");
+        })?;
+        let Some(line) = lines.next() else {
+            println!();
+            return Ok(());
+        };
+        let line = line.map_err(|err| {
+            eprintln!("ERROR: could not read the input: {err}");
+        })?;
+        let line = line.trim();
+
+        let (command, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let args = args.trim();
+        match command {
+            "" => {}
+            ":explain" => explain(model, dir_path, args),
+            ":terms" => terms(model, args),
+            ":doc" => doc(model, dir_path, args),
+            ":stats" => stats(model),
+            ":help" => help(),
+            ":quit" | ":q" => return Ok(()),
+            _ if command.starts_with(':') => println!("ERROR: unknown command {command}. Type :help for the list of the commands."),
+            _ => search(model, line),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// A path in the temporary directory for the tests that need the real files. Whatever ends up
+// there is removed once the test is over.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+pub struct ScratchPath(pub PathBuf);
+
+impl ScratchPath {
+    /// Nothing is created yet. The name must be unique among the tests, the id of the process
+    /// keeps the concurrent runs apart.
+    pub fn new(name: &str) -> Self {
+        ScratchPath(env::temp_dir().join(format!("seroost-{pid}-{name}", pid = process::id())))
+    }
+
+    /// A directory with the files at the relative paths.
+    pub fn dir(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = ScratchPath::new(name);
+        fs::create_dir_all(&dir.0).unwrap();
+        for (path, content) in files {
+            let path = dir.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    /// A file with the content.
+    pub fn file(name: &str, content: &str) -> Self {
+        let file = ScratchPath::new(name);
+        fs::write(&file.0, content).unwrap();
+        file
+    }
+}
+
+impl Drop for ScratchPath {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            let _ = fs::remove_dir_all(&self.0);
+        } else {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+}
@@ -1,21 +1,74 @@
-use std::str;
+use std::fs;
 use std::io;
 use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
 
 use super::model::*;
+use super::analyzer::FieldAnalyzers;
+use super::language::Language;
+use super::query::{self, Query};
+use super::snippet::{Snippet, SnippetDraft};
 
 use tiny_http::{Server, Request, Response, Header, Method, StatusCode};
 
+/// The error of the `/api/*` endpoints. Serialized as `{"error": {...}}`.
+#[derive(Serialize)]
+struct ApiError {
+    /// Machine-readable kind of the error.
+    code: &'static str,
+    message: String,
+    /// The field of the request that caused the error, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<String>,
+}
+
+impl ApiError {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), field: None }
+    }
+
+    fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+}
+
+fn serve_api_error(request: Request, status_code: u16, error: ApiError) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Body {
+        error: ApiError,
+    }
+
+    let json = serde_json::to_string(&Body { error }).expect("ApiError is always serializable");
+    let content_type_header = Header::from_bytes("Content-Type", "application/json")
+        .expect("That we didn't put any garbage in the headers");
+    request.respond(Response::from_string(json).with_header(content_type_header).with_status_code(StatusCode(status_code)))
+}
+
 fn serve_404(request: Request) -> io::Result<()> {
+    if request.url().starts_with("/api/") {
+        let message = format!("no such endpoint: {url}", url = request.url());
+        return serve_api_error(request, 404, ApiError::new("not_found", message));
+    }
     request.respond(Response::from_string("404").with_status_code(StatusCode(404)))
 }
 
+fn serve_405(request: Request) -> io::Result<()> {
+    let message = format!("method {method} is not allowed for {url}", method = request.method(), url = request.url());
+    serve_api_error(request, 405, ApiError::new("method_not_allowed", message))
+}
+
 fn serve_500(request: Request) -> io::Result<()> {
-    request.respond(Response::from_string("500").with_status_code(StatusCode(500)))
+    serve_api_error(request, 500, ApiError::new("internal_error", "internal server error"))
 }
 
-fn serve_400(request: Request, message: &str) -> io::Result<()> {
-    request.respond(Response::from_string(format!("400: {message}")).with_status_code(StatusCode(400)))
+fn serve_400(request: Request, error: ApiError) -> io::Result<()> {
+    serve_api_error(request, 400, error)
 }
 
 fn serve_bytes(request: Request, bytes: &[u8], content_type: &str) -> io::Result<()> {
@@ -24,27 +77,222 @@ fn serve_bytes(request: Request, bytes: &[u8], content_type: &str) -> io::Result
     request.respond(Response::from_data(bytes).with_header(content_type_header))
 }
 
-// TODO: the errors of serve_api_search should probably return JSON
-// 'Cause that's what expected from them.
+/// Shared with `search` subcommand.
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+struct SearchRequest {
+    query: String,
+    offset: usize,
+    limit: usize,
+    filters: SearchFilters,
+}
+
+#[derive(Default)]
+struct SearchFilters {
+    /// Extensions of the files without the dot, e.g. "pdf".
+    file_types: Option<Vec<String>>,
+    path_prefix: Option<PathBuf>,
+    /// Seconds since UNIX epoch.
+    modified_after: Option<u64>,
+    modified_before: Option<u64>,
+}
+
+impl SearchFilters {
+    fn matches(&self, doc: &DocInfo) -> bool {
+        if let Some(file_types) = &self.file_types {
+            let file_type = file_type(doc.path);
+            if !file_types.iter().any(|t| Some(t.as_str()) == file_type) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.path_prefix {
+            if !doc.path.starts_with(prefix) {
+                return false;
+            }
+        }
+        let modified = unix_secs(doc.last_modified);
+        if self.modified_after.map(|after| modified < after).unwrap_or(false) {
+            return false;
+        }
+        if self.modified_before.map(|before| modified >= before).unwrap_or(false) {
+            return false;
+        }
+        true
+    }
+}
+
+/// Removes the field from the object and deserializes it. `field_path` is reported in the errors.
+fn take_field<T: DeserializeOwned>(object: &mut Map<String, Value>, name: &str, field_path: &str) -> Result<Option<T>, ApiError> {
+    match object.remove(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value).map(Some).map_err(|err| {
+            ApiError::new("invalid_field", format!("invalid value of {field_path}: {err}")).with_field(field_path)
+        }),
+    }
+}
+
+fn expect_object(value: Value, field_path: Option<&str>) -> Result<Map<String, Value>, ApiError> {
+    match value {
+        Value::Object(object) => Ok(object),
+        _ => match field_path {
+            Some(field_path) => Err(ApiError::new("invalid_field", format!("{field_path} must be an object")).with_field(field_path)),
+            None => Err(ApiError::new("invalid_json", "request body must be a JSON object")),
+        }
+    }
+}
+
+fn reject_unknown_fields(object: &Map<String, Value>, prefix: &str) -> Result<(), ApiError> {
+    match object.keys().next() {
+        Some(name) => Err(ApiError::new("unknown_field", format!("unknown field {prefix}{name}")).with_field(format!("{prefix}{name}"))),
+        None => Ok(()),
+    }
+}
+
+fn parse_search_request(body: &[u8]) -> Result<SearchRequest, ApiError> {
+    let body = serde_json::from_slice(body).map_err(|err| {
+        ApiError::new("invalid_json", format!("request body is not a valid JSON: {err}"))
+    })?;
+    let mut object = expect_object(body, None)?;
+
+    let query = take_field(&mut object, "query", "query")?.ok_or_else(|| {
+        ApiError::new("missing_field", "query is required").with_field("query")
+    })?;
+    let offset = take_field(&mut object, "offset", "offset")?.unwrap_or(0);
+    let limit = take_field(&mut object, "limit", "limit")?.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if limit > MAX_SEARCH_LIMIT {
+        let error = ApiError::new("invalid_field", format!("limit must not exceed {MAX_SEARCH_LIMIT}"));
+        return Err(error.with_field("limit"));
+    }
+    let mut filters = SearchFilters::default();
+    if let Some(value) = object.remove("filters").filter(|value| !value.is_null()) {
+        let mut object = expect_object(value, Some("filters"))?;
+        filters.file_types = take_field(&mut object, "file_types", "filters.file_types")?;
+        filters.path_prefix = take_field(&mut object, "path_prefix", "filters.path_prefix")?;
+        filters.modified_after = take_field(&mut object, "modified_after", "filters.modified_after")?;
+        filters.modified_before = take_field(&mut object, "modified_before", "filters.modified_before")?;
+        reject_unknown_fields(&object, "filters.")?;
+    }
+    reject_unknown_fields(&object, "")?;
+
+    Ok(SearchRequest { query, offset, limit, filters })
+}
+
+fn parse_query(query: &str, analyzers: &FieldAnalyzers, languages: &[Language]) -> Result<Query, ApiError> {
+    query::parse(&query.chars().collect::<Vec<_>>(), analyzers, languages).map_err(|err| {
+        ApiError::new("invalid_query", err.to_string()).with_field("query")
+    })
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    total: usize,
+    took_ms: f64,
+    offset: usize,
+    limit: usize,
+    hits: Vec<SearchHit>,
+}
+
+/// A hit of the API and of `search --json`.
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub path: PathBuf,
+    pub score: f32,
+    file_type: Option<String>,
+    /// Size of the file in bytes at the moment of the search. `None` if the file is gone.
+    size: Option<u64>,
+    /// Seconds since UNIX epoch.
+    last_modified: u64,
+    /// ISO 639-1 code of the detected language.
+    language: Option<&'static str>,
+    pub snippets: Vec<Snippet>,
+}
+
+impl SearchHit {
+    /// Takes what is needed from the document, so the hit can be finished without the model.
+    pub fn new(doc: &DocInfo, score: f32) -> Self {
+        SearchHit {
+            path: doc.path.to_path_buf(),
+            score,
+            file_type: file_type(doc.path).map(str::to_string),
+            size: None,
+            last_modified: unix_secs(doc.last_modified),
+            language: doc.language.map(|language| language.code()),
+            snippets: vec![],
+        }
+    }
+
+    /// Checks the file and makes the snippets. Does not need the lock of the model.
+    pub fn finish(mut self, snippets: SnippetDraft) -> Self {
+        self.size = fs::metadata(&self.path).map(|metadata| metadata.len()).ok();
+        self.snippets = snippets.finish();
+        self
+    }
+}
+
+fn file_type(path: &Path) -> Option<&str> {
+    path.extension().and_then(|extension| extension.to_str())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+/// The part of the matches requested by `offset` and `limit`. Both come from the client, so any
+/// of them may be out of the range.
+fn page<T>(matches: &[T], offset: usize, limit: usize) -> &[T] {
+    let start = offset.min(matches.len());
+    let end = offset.saturating_add(limit).min(matches.len());
+    &matches[start..end]
+}
+
 fn serve_api_search(model: Arc<Mutex<Model>>, mut request: Request) -> io::Result<()> {
+    let started = Instant::now();
+
     let mut buf = Vec::new();
     if let Err(err) = request.as_reader().read_to_end(&mut buf) {
         eprintln!("ERROR: could not read the body of the request: {err}");
         return serve_500(request);
     }
 
-    let body = match str::from_utf8(&buf) {
-        Ok(body) => body.chars().collect::<Vec<_>>(),
-        Err(err) => {
-            eprintln!("ERROR: could not interpret body as UTF-8 string: {err}");
-            return serve_400(request, "Body must be a valid UTF-8 string");
-        }
+    let search = match parse_search_request(&buf) {
+        Ok(search) => search,
+        Err(err) => return serve_400(request, err),
+    };
+
+    let (analyzers, languages) = {
+        let model = model.lock().unwrap();
+        (model.analyzers().clone(), model.languages())
+    };
+    let query = match parse_query(&search.query, &analyzers, &languages) {
+        Ok(query) => query,
+        Err(err) => return serve_400(request, err),
     };
 
-    let model = model.lock().unwrap();
-    let result = model.search_query(&body);
+    // Only the drafts of the snippets are made under the lock, the rest of the work is done without it
+    let (total, hits, snippets) = {
+        let model = model.lock().unwrap();
+        let matches = model.search_query(&query)
+            .into_iter()
+            .filter_map(|(id, score)| Some((id, score, model.doc(id)?)))
+            .filter(|(_, _, doc)| search.filters.matches(doc))
+            .collect::<Vec<_>>();
+        let page = page(&matches, search.offset, search.limit);
+        let snippets = model.snippets(&page.iter().map(|(id, _, _)| *id).collect::<Vec<_>>(), &query);
+        let hits = page.iter().map(|(_, score, doc)| SearchHit::new(doc, *score)).collect::<Vec<_>>();
+        (matches.len(), hits, snippets)
+    };
+    let hits = hits.into_iter().zip(snippets).map(|(hit, snippets)| hit.finish(snippets)).collect();
+
+    let response = SearchResponse {
+        total,
+        took_ms: started.elapsed().as_secs_f64()*1000.0,
+        offset: search.offset,
+        limit: search.limit,
+        hits,
+    };
 
-    let json = match serde_json::to_string(&result.iter().take(20).collect::<Vec<_>>()) {
+    let json = match serde_json::to_string(&response) {
         Ok(json) => json,
         Err(err) => {
             eprintln!("ERROR: could not convert search results to JSON: {err}");
@@ -58,19 +306,28 @@ fn serve_api_search(model: Arc<Mutex<Model>>, mut request: Request) -> io::Resul
 }
 
 fn serve_api_stats(model: Arc<Mutex<Model>>, request: Request) -> io::Result<()> {
-    use serde::Serialize;
-
     #[derive(Default, Serialize)]
     struct Stats {
         docs_count: usize,
         terms_count: usize,
+        /// The specs of the analyzers by the name of the field.
+        analyzers: Map<String, Value>,
+        /// The amounts of the documents by the code of the detected language.
+        languages: Map<String, Value>,
     }
 
     let mut stats: Stats = Default::default();
     {
         let model = model.lock().unwrap();
-        stats.docs_count = model.docs.len();
-        stats.terms_count = model.df.len();
+        stats.docs_count = model.docs_count();
+        stats.terms_count = model.terms_count();
+        for field in Field::ALL {
+            let analyzer = model.analyzers()[field.index()].to_string();
+            stats.analyzers.insert(field.name().to_string(), Value::String(analyzer));
+        }
+        for language in model.languages() {
+            stats.languages.insert(language.code().to_string(), Value::from(model.language_count(language)));
+        }
     }
 
     let json = match serde_json::to_string(&stats) {
@@ -96,6 +353,9 @@ fn serve_request(model: Arc<Mutex<Model>>, request: Request) -> io::Result<()> {
         (Method::Get, "/api/stats") => {
             serve_api_stats(model, request)
         }
+        (_, "/api/search") | (_, "/api/stats") => {
+            serve_405(request)
+        }
         (Method::Get, "/index.js") => {
             serve_bytes(request, include_bytes!("index.js"), "text/javascript; charset=utf-8")
         }
@@ -124,3 +384,97 @@ pub fn start(address: &str, model: Arc<Mutex<Model>>) -> Result<(), ()> {
     eprintln!("ERROR: the server socket has shutdown");
     Err(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_out_of_range() {
+        let matches = [1, 2, 3, 4, 5];
+        assert_eq!(page(&matches, 0, 2), [1, 2]);
+        assert_eq!(page(&matches, 3, 20), [4, 5]);
+        assert!(page(&matches, 5, 20).is_empty());
+        assert!(page(&matches, 100, 20).is_empty());
+        assert!(page(&matches, usize::MAX, MAX_SEARCH_LIMIT).is_empty());
+        assert_eq!(page(&matches, 2, usize::MAX), [3, 4, 5]);
+    }
+
+    /// The field of the error of the request, or the request if it is valid.
+    fn parse(body: &str) -> Result<SearchRequest, Option<String>> {
+        parse_search_request(body.as_bytes()).map_err(|err| err.field)
+    }
+
+    #[test]
+    fn search_request_defaults() {
+        let search = parse(r#"{"query": "rust"}"#).unwrap();
+        assert_eq!(search.query, "rust");
+        assert_eq!((search.offset, search.limit), (0, DEFAULT_SEARCH_LIMIT));
+        assert!(search.filters.file_types.is_none() && search.filters.path_prefix.is_none());
+        assert!(search.filters.modified_after.is_none() && search.filters.modified_before.is_none());
+
+        // `null` is the same as a missing field
+        let search = parse(r#"{"query": "rust", "offset": null, "limit": null, "filters": null}"#).unwrap();
+        assert_eq!((search.offset, search.limit), (0, DEFAULT_SEARCH_LIMIT));
+
+        let search = parse(r#"{
+            "query": "rust",
+            "offset": 40,
+            "limit": 100,
+            "filters": {"file_types": ["md", "pdf"], "path_prefix": "/docs", "modified_after": 1700000000}
+        }"#).unwrap();
+        assert_eq!((search.offset, search.limit), (40, MAX_SEARCH_LIMIT));
+        assert_eq!(search.filters.file_types, Some(vec!["md".to_string(), "pdf".to_string()]));
+        assert_eq!(search.filters.path_prefix, Some(PathBuf::from("/docs")));
+        assert_eq!((search.filters.modified_after, search.filters.modified_before), (Some(1700000000), None));
+    }
+
+    #[test]
+    fn search_request_fields_of_errors() {
+        let cases = [
+            (r#"{"query": "rust", "limit": 101}"#, "limit"),
+            (r#"{"query": "rust", "limit": -1}"#, "limit"),
+            (r#"{"query": "rust", "offset": "10"}"#, "offset"),
+            (r#"{"query": 42}"#, "query"),
+            (r#"{}"#, "query"),
+            (r#"{"query": "rust", "page": 2}"#, "page"),
+            (r#"{"query": "rust", "filters": []}"#, "filters"),
+            (r#"{"query": "rust", "filters": {"file_types": "md"}}"#, "filters.file_types"),
+            (r#"{"query": "rust", "filters": {"path_prefix": 1}}"#, "filters.path_prefix"),
+            (r#"{"query": "rust", "filters": {"modified_after": "yesterday"}}"#, "filters.modified_after"),
+            (r#"{"query": "rust", "filters": {"modified_before": 1.5}}"#, "filters.modified_before"),
+            (r#"{"query": "rust", "filters": {"size": 10}}"#, "filters.size"),
+        ];
+        for (body, field) in cases {
+            match parse(body) {
+                Ok(_) => panic!("{body}: expected an error"),
+                Err(err) => assert_eq!(err.as_deref(), Some(field), "{body}"),
+            }
+        }
+    }
+
+    #[test]
+    fn error_codes() {
+        let cases = [
+            (r#"{"query": "rust""#, "invalid_json"),
+            (r#"["rust"]"#, "invalid_json"),
+            (r#"{"limit": 10}"#, "missing_field"),
+            (r#"{"query": "rust", "limit": 1000}"#, "invalid_field"),
+            (r#"{"query": "rust", "filters": "md"}"#, "invalid_field"),
+            (r#"{"query": "rust", "filters": {"modified_after": -1}}"#, "invalid_field"),
+            (r#"{"query": "rust", "sort": "date"}"#, "unknown_field"),
+            (r#"{"query": "rust", "filters": {"type": "md"}}"#, "unknown_field"),
+            (r#"{"query": "rust OR"}"#, "invalid_query"),
+            (r#"{"query": "(rust"}"#, "invalid_query"),
+            (r#"{"query": "lang:xx"}"#, "invalid_query"),
+        ];
+        for (body, code) in cases {
+            let result = parse_search_request(body.as_bytes())
+                .and_then(|search| parse_query(&search.query, &FieldAnalyzers::default(), &[]));
+            match result {
+                Ok(_) => panic!("{body}: expected an error"),
+                Err(err) => assert_eq!(err.code, code, "{body}: {message}", message = err.message),
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
+use serde::Serialize;
+
+/// Length of a snippet counted in tokens.
+const SNIPPET_LEN: usize = 24;
+const MAX_SNIPPETS: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct Snippet {
+    pub text: String,
+    /// Ranges of the matched terms in `text` counted in chars.
+    pub highlights: Vec<(usize, usize)>,
+}
+
+impl Snippet {
+    /// Renders the text with the highlights wrapped into `mark_start` and `mark_end`.
+    pub fn render(&self, mark_start: &str, mark_end: &str) -> String {
+        let chars = self.text.chars().collect::<Vec<_>>();
+        let mut result = String::new();
+        let mut cursor = 0;
+        for (start, end) in &self.highlights {
+            result.extend(&chars[cursor..*start]);
+            result.push_str(mark_start);
+            result.extend(&chars[*start..*end]);
+            result.push_str(mark_end);
+            cursor = *end;
+        }
+        result.extend(&chars[cursor..]);
+        result
+    }
+}
+
+/// Byte ranges of the tokens of the body in its text by their positions. Encoded as pairs of
+/// little-endian u32, so the ones of the mapped index file are used as is.
+#[derive(Clone, Copy, Default)]
+pub struct Spans<'a>(pub &'a [u8]);
+
+impl Spans<'_> {
+    const ENTRY_SIZE: usize = 8;
+
+    pub fn push(spans: &mut Vec<u8>, range: Range<usize>) {
+        for offset in [range.start, range.end] {
+            spans.extend(u32::try_from(offset).unwrap_or(u32::MAX).to_le_bytes());
+        }
+    }
+
+    /// The number of the tokens.
+    pub fn count(&self) -> usize {
+        self.0.len() / Self::ENTRY_SIZE
+    }
+
+    pub fn get(&self, position: usize) -> Option<Range<usize>> {
+        let entry = self.0.get(position*Self::ENTRY_SIZE..(position + 1)*Self::ENTRY_SIZE)?;
+        let start = u32::from_le_bytes(entry[..4].try_into().ok()?) as usize;
+        let end = u32::from_le_bytes(entry[4..].try_into().ok()?) as usize;
+        Some(start..end)
+    }
+}
+
+/// The windows of the text of a document with the matches in them. Extracting it needs the model,
+/// turning it into the snippets does not, so it's done without holding the lock of the model.
+#[derive(Default)]
+pub struct SnippetDraft {
+    /// The texts of the windows and the byte ranges of the matches in them.
+    windows: Vec<(String, Vec<Range<usize>>)>,
+}
+
+/// Picks the regions of the text with the most matches. `matches` are the positions of the
+/// matched terms along with the terms sorted by the positions.
+pub fn draft(text: &str, spans: Spans, matches: &[(usize, &str)]) -> SnippetDraft {
+    let windows = pick_windows(matches, spans.count());
+    let windows = windows.into_iter().filter_map(|window| {
+        let from = spans.get(window.start)?.start;
+        let to = spans.get(window.end - 1)?.end;
+        let start = matches.partition_point(|(position, _)| *position < window.start);
+        let highlights = matches[start..]
+            .iter()
+            .take_while(|(position, _)| *position < window.end)
+            .filter_map(|(position, _)| spans.get(*position))
+            .filter(|span| from <= span.start && span.end <= to)
+            .map(|span| span.start - from..span.end - from)
+            .collect();
+        Some((text.get(from..to)?.to_string(), highlights))
+    });
+    SnippetDraft { windows: windows.collect() }
+}
+
+fn pick_windows(matches: &[(usize, &str)], tokens_count: usize) -> Vec<Range<usize>> {
+    // Score of a window is the amount of distinct matched terms first and all the matches second.
+    // The windows start at a match, so the snippets are not cut in the middle of it.
+    let scores = (0..matches.len()).map(|i| {
+        let start = matches[i].0;
+        let window = matches[i..].iter().take_while(|(position, _)| *position < start + SNIPPET_LEN);
+        let distinct = window.clone().map(|(_, term)| term).collect::<HashSet<_>>().len();
+        (distinct, window.count())
+    }).collect::<Vec<_>>();
+
+    let mut windows = Vec::<Range<usize>>::new();
+    let overlaps = |windows: &[Range<usize>], range: Range<usize>| windows.iter().any(|window| window.start < range.end && range.start < window.end);
+    while windows.len() < MAX_SNIPPETS {
+        let best = (0..matches.len())
+            .filter(|i| !overlaps(&windows, matches[*i].0..(matches[*i].0 + SNIPPET_LEN).min(tokens_count)))
+            .max_by_key(|i| (scores[*i], Reverse(matches[*i].0)));
+        let Some(best) = best else {
+            break;
+        };
+        let best = matches[best].0;
+        // Give the first match a bit of context on the left
+        let mut start = best.saturating_sub(SNIPPET_LEN/4);
+        while overlaps(&windows, start..best) {
+            start += 1;
+        }
+        windows.push(start..(start + SNIPPET_LEN).min(tokens_count));
+    }
+    if windows.is_empty() && tokens_count > 0 {
+        // Nothing matched (e.g. the query was a negation). Show the beginning of the text.
+        windows.push(0..SNIPPET_LEN.min(tokens_count));
+    }
+    windows.sort_by_key(|window| window.start);
+    windows
+}
+
+impl SnippetDraft {
+    pub fn finish(self) -> Vec<Snippet> {
+        self.windows.into_iter().map(|(text, matches)| {
+            // Collapse the whitespace, the extracted text tends to have a lot of it. The offsets
+            // map the bytes of the text to the chars of the snippet.
+            let mut snippet = String::new();
+            let mut offsets = vec![0; text.len()];
+            let mut len = 0;
+            let mut prev_space = false;
+            for (i, c) in text.char_indices() {
+                offsets[i..i + c.len_utf8()].fill(len);
+                if c.is_whitespace() {
+                    if !prev_space {
+                        snippet.push(' ');
+                        len += 1;
+                    }
+                    prev_space = true;
+                } else {
+                    snippet.push(c);
+                    len += 1;
+                    prev_space = false;
+                }
+            }
+            let highlights = matches
+                .into_iter()
+                .filter(|span| !span.is_empty())
+                .map(|span| (offsets[span.start], offsets[span.end - 1] + 1))
+                .collect();
+            Snippet { text: snippet, highlights }
+        }).collect()
+    }
+}
@@ -1,3 +1,8 @@
 // Have a look at build.rs
 //include!(concat!(env!("OUT_DIR"), "/lang_include.rs"));
 pub mod english_stemmer;
+pub mod french_stemmer;
+pub mod german_stemmer;
+pub mod portuguese_stemmer;
+pub mod russian_stemmer;
+pub mod spanish_stemmer;
@@ -0,0 +1,140 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode};
+use notify::Watcher as _;
+
+use super::model::Model;
+use super::{add_file_to_model, add_folder_to_model, parser_by_extension, remove_stale_documents, save_index, IndexingStats};
+use super::file_filter::FileFilter;
+
+/// A change of a file usually comes as a burst of events. Wait for it to settle down.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// A folder that never settles down (a build, a log being written) must not hold the changes back
+/// forever.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(2);
+/// How often the changes are written to the index file.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct Watcher {
+    filter: FileFilter,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl Watcher {
+    /// Starts watching the folder. The changes are queued until `run` is called, so the ones made
+    /// during the initial indexing are not lost.
+    pub fn new(filter: FileFilter) -> Result<Self, ()> {
+        let dir_path = filter.root();
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|err| {
+            eprintln!("ERROR: could not create filesystem watcher: {err}");
+        })?;
+        watcher.watch(dir_path, RecursiveMode::Recursive).map_err(|err| {
+            eprintln!("ERROR: could not watch directory {dir_path}: {err}",
+                      dir_path = dir_path.display());
+        })?;
+        Ok(Self {
+            filter,
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    fn collect(&self, event: notify::Result<Event>, changed: &mut BTreeSet<PathBuf>) {
+        match event {
+            Ok(event) if event.kind.is_access() => {}
+            Ok(event) => {
+                for path in event.paths {
+                    // The rules of an ignore file apply to its whole directory
+                    let path = match path.parent() {
+                        Some(dir) if self.filter.forget_ignore_file(&path) => dir.to_path_buf(),
+                        _ => path,
+                    };
+                    // A path that is gone may have been a directory, its documents have to be removed
+                    let is_dir = path.is_dir();
+                    let relevant = is_dir || parser_by_extension(&path).is_some() || !path.exists();
+                    if relevant && !self.filter.is_ignored(&path, is_dir) {
+                        changed.insert(path);
+                    }
+                }
+            }
+            Err(err) => eprintln!("WARNING: filesystem watcher error: {err}"),
+        }
+    }
+
+    /// Brings the documents of the path up to date with the filesystem. Returns whether the model
+    /// has changed.
+    fn update(&self, model: &Arc<Mutex<Model>>, path: &Path) -> bool {
+        let mut stats = IndexingStats::default();
+        match fs::metadata(path) {
+            // The files of the directory may have become ignored or not ignored
+            Ok(metadata) if metadata.is_dir() => {
+                remove_stale_documents(&self.filter, path, model, &mut stats);
+                add_folder_to_model(&self.filter, path, Arc::clone(model), &mut stats).ok();
+            }
+            Ok(metadata) => match metadata.modified() {
+                Ok(last_modified) => add_file_to_model(path.to_path_buf(), last_modified, model, &mut stats),
+                Err(err) => eprintln!("ERROR: could not get the last modification date of file {path}: {err}",
+                                      path = path.display()),
+            }
+            // Deleted or renamed
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let removed = model.lock().unwrap().remove_path(path);
+                if removed > 0 {
+                    println!("Removed {removed} documents of {path:?}");
+                }
+                return removed > 0;
+            }
+            Err(err) => eprintln!("ERROR: could not get the metadata of file {path}: {err}",
+                                  path = path.display()),
+        }
+        stats.model_changed()
+    }
+
+    /// Applies the changes of the folder to the model until the watcher stops. `dirty` tells that
+    /// the model already has the changes that are not in the index file.
+    pub fn run(self, model: Arc<Mutex<Model>>, index_path: &Path, mut dirty: bool) -> Result<(), ()> {
+        let mut last_save = Instant::now();
+        loop {
+            let mut changed = BTreeSet::new();
+            match self.events.recv_timeout(SAVE_INTERVAL) {
+                Ok(event) => {
+                    self.collect(event, &mut changed);
+                    let first_event = Instant::now();
+                    while first_event.elapsed() < MAX_BATCH_DELAY {
+                        let timeout = DEBOUNCE.min(MAX_BATCH_DELAY - first_event.elapsed());
+                        match self.events.recv_timeout(timeout) {
+                            Ok(event) => self.collect(event, &mut changed),
+                            Err(_) => break,
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    eprintln!("ERROR: filesystem watcher has stopped");
+                    return Err(());
+                }
+            }
+
+            for path in &changed {
+                dirty |= self.update(&model, path);
+            }
+
+            if dirty && last_save.elapsed() >= SAVE_INTERVAL {
+                // Keep the changes around and try again later if it did not work out
+                if let Ok(saved) = save_index(&model, index_path) {
+                    dirty = !saved;
+                    last_save = Instant::now();
+                }
+            }
+        }
+    }
+}